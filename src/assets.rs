@@ -4,6 +4,7 @@
 use std::io::Write;
 
 use image::{DynamicImage, GenericImageView};
+use log::warn;
 
 type OptIcon = Option<String>;
 
@@ -33,7 +34,7 @@ impl ServerIcons {
         if image.dimensions() == (64, 64) {
             return Self::encode(image)
         }
-        println!("Asset {} does not match the dimensions. (64x64 pixels)", name);
+        warn!("[assets] asset {} does not match the dimensions. (64x64 pixels)", name);
         None
     }
 
@@ -43,7 +44,7 @@ impl ServerIcons {
         if let Ok(image) = result {
             return Self::encode_valid(name, image)
         }
-        println!("Asset {} does not exist in '{}' or has invalid format.", name, path);
+        warn!("[assets] asset {} does not exist in '{}' or has invalid format.", name, path);
         None
     }
 
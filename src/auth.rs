@@ -0,0 +1,35 @@
+//! Room password authentication.
+//!
+//! The server sends a random challenge and the client proves it knows
+//! the password with an HMAC of that challenge.
+//! The password itself never goes over the wire.
+
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+/// Random bytes the client has to sign.
+pub type Challenge = [u8; 32];
+
+/// HMAC-SHA256 of the challenge keyed with the password.
+pub type Proof = [u8; 32];
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Creates a new random challenge.
+pub fn new_challenge() -> Challenge {
+    rand::random()
+}
+
+/// Signs the challenge with the password.
+pub fn prove(password: &str, challenge: &Challenge) -> Proof {
+    let mut mac = HmacSha256::new_from_slice(password.as_bytes()).expect("HMAC accepts keys of any size.");
+    mac.update(challenge);
+    mac.finalize().into_bytes().into()
+}
+
+/// Checks the proof in constant time.
+pub fn verify(password: &str, challenge: &Challenge, proof: &Proof) -> bool {
+    let mut mac = HmacSha256::new_from_slice(password.as_bytes()).expect("HMAC accepts keys of any size.");
+    mac.update(challenge);
+    mac.verify_slice(proof).is_ok()
+}
@@ -0,0 +1,179 @@
+//! Headless HiveSearch server.
+//! Runs a hive without the desktop application, e.g. on an always-on box.
+
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::process;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use async_std::channel::unbounded;
+use async_std::task::block_on;
+use clap::Parser;
+use log::{error, info, LevelFilter};
+
+use hive_search::heartbeat::HeartbeatConfig;
+use hive_search::observer::{ChannelObserver, SessionEvent};
+use hive_search::recording::{self, Replayed, StateEvent};
+use hive_search::server::{self, HostPolicy, ServerConfig};
+use hive_search::sync::PauseToken;
+
+/// Runs a HiveSearch hive without the desktop application.
+#[derive(Parser)]
+#[clap(version)]
+struct Args {
+    /// Address to listen on, [::]:PORT takes both IPv4 and IPv6.
+    #[clap(long, default_value = "0.0.0.0:2137")]
+    listen: SocketAddr,
+    /// Room password, the room is open if not set.
+    #[clap(long)]
+    password: Option<String>,
+    /// Encrypt connections with a self-signed certificate.
+    #[clap(long)]
+    tls: bool,
+    /// Seconds between heartbeats.
+    #[clap(long, default_value = "2", value_parser = seconds)]
+    heartbeat_interval: Duration,
+    /// Seconds of silence after which a client is dropped.
+    #[clap(long, default_value = "10", value_parser = seconds)]
+    heartbeat_timeout: Duration,
+    /// Announce the hive on the local network under the name.
+    #[clap(long, value_name = "NAME")]
+    announce: Option<String>,
+    /// Relay games through the hive, hosts do not have to forward ports.
+    #[clap(long)]
+    relay: bool,
+    /// Games shown when several are open: show-all, first-opened or most-recent.
+    #[clap(long, default_value = "show-all", possible_values = ["show-all", "first-opened", "most-recent"])]
+    policy: String,
+    /// Show games of these searchers first, in the given order. Overrides --policy.
+    #[clap(long, value_name = "NICKNAME", multiple_occurrences = true)]
+    priority: Vec<String>,
+    /// Seconds an empty room keeps its games and timer.
+    #[clap(long, default_value = "600", value_parser = seconds)]
+    room_expiry: Duration,
+    /// Append every state change to the file.
+    #[clap(long, value_name = "FILE")]
+    record: Option<PathBuf>,
+    /// Print what happened in a recording instead of running a hive.
+    #[clap(long, value_name = "FILE")]
+    replay: Option<PathBuf>,
+    /// One of: off, error, warn, info, debug, trace.
+    #[clap(long, default_value = "info")]
+    log_level: LevelFilter,
+}
+
+fn main() {
+    let args = Args::parse();
+    env_logger::Builder::new()
+        .filter_level(args.log_level)
+        .init();
+
+    if let Some(path) = &args.replay {
+        match recording::load(path) {
+            Ok(records) => print_replay(block_on(server::replay(records))),
+            Err(error) => {
+                error!("[hive server] failed to read {}: {}", path.display(), error);
+                process::exit(1);
+            }
+        }
+        return
+    }
+
+    let config = ServerConfig {
+        address: args.listen,
+        password: args.password,
+        tls: args.tls,
+        heartbeat: HeartbeatConfig {
+            interval: args.heartbeat_interval,
+            timeout: args.heartbeat_timeout,
+        },
+        recording: args.record,
+        discovery: args.announce,
+        relay: args.relay,
+        policy: policy(&args.policy, args.priority),
+        room_expiry: args.room_expiry,
+        // Headless hives have no host client.
+        host_key: None,
+    };
+    let stop_token = Arc::new(PauseToken::new(true));
+    let pause_token = Arc::new(PauseToken::new(false));
+    let (observer, events) = ChannelObserver::new();
+    // Nobody moderates a headless hive, the sender only keeps the server running.
+    let (_commands, commands_recv) = unbounded();
+    server::start(
+        Arc::new(observer),
+        stop_token,
+        pause_token,
+        config,
+        commands_recv,
+    );
+
+    while let Ok(event) = block_on(events.recv()) {
+        match event {
+            SessionEvent::Members(members) => {
+                info!("[hive server] {} users connected", members.len());
+            }
+            SessionEvent::Failed(reason) => {
+                error!("[hive server] {}", reason);
+                process::exit(1);
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Positive number of seconds from the command line.
+fn seconds(value: &str) -> Result<Duration, String> {
+    let seconds: f32 = value.parse().map_err(|_| format!("{} is not a number", value))?;
+    match Duration::try_from_secs_f32(seconds) {
+        Ok(duration) if seconds > 0. => Ok(duration),
+        _ => Err(format!("{} is not a positive number of seconds", value)),
+    }
+}
+
+/// Policy from the command line.
+fn policy(name: &str, priority: Vec<String>) -> HostPolicy {
+    if !priority.is_empty() {
+        return HostPolicy::Priority(priority)
+    }
+    match name {
+        "first-opened" => HostPolicy::FirstOpened,
+        "most-recent" => HostPolicy::MostRecent,
+        _ => HostPolicy::ShowAll,
+    }
+}
+
+/// Prints every recorded event and the messages it caused.
+/// Times are relative to the start of the server run.
+fn print_replay(replayed: Vec<Replayed>) {
+    let mut started = SystemTime::UNIX_EPOCH;
+    for Replayed { record, sent } in replayed {
+        if let StateEvent::Started(_) = record.event {
+            started = record.at;
+        }
+        let time = record.at.duration_since(started).unwrap_or_default();
+        let event = match record.event {
+            StateEvent::Started(protocol_version) => format!("hive started (protocol {})", protocol_version),
+            StateEvent::Policy(policy) => format!("policy {:?}", policy),
+            StateEvent::Joined(id, nickname, ip, room) => format!("#{} {} joined {:?} from {}", id, nickname, room, ip),
+            StateEvent::Left(id) => format!("#{} left", id),
+            StateEvent::Message(id, message) => format!("#{} sent {:?}", id, message),
+            StateEvent::Command(command) => format!("host {:?}", command),
+            StateEvent::Expired(room) => format!("room {:?} expired", room),
+        };
+        println!("[{:>10.3}] {}", time.as_secs_f64(), event);
+        // Broadcasts are printed once, with all recipients.
+        let mut messages: Vec<(String, Vec<String>)> = Vec::new();
+        for (id, message) in sent {
+            let message = format!("{:?}", message);
+            match messages.iter_mut().find(|(known, _)| *known == message) {
+                Some((_, recipients)) => recipients.push(format!("#{}", id)),
+                None => messages.push((message, vec![format!("#{}", id)])),
+            }
+        }
+        for (message, recipients) in messages {
+            println!("{:>12} -> {}: {}", "", recipients.join(", "), message);
+        }
+    }
+}
@@ -2,63 +2,210 @@
 Client stuff.
 */
 
-use std::net::SocketAddr;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::str::FromStr;
 use std::sync::Arc;
 use std::time::Duration;
 
-use async_std::channel::{unbounded, Receiver, Sender};
+use async_std::channel::{bounded, unbounded, Receiver, Sender};
+use async_std::future;
 use async_std::io::timeout;
-use async_std::net::TcpStream;
-use async_std::task::{block_on, spawn};
-use druid::{ExtEventSink, Target};
+use async_std::net::{TcpStream, ToSocketAddrs};
+use async_std::task::{block_on, sleep, spawn};
 use futures::{FutureExt, SinkExt, TryStreamExt, pin_mut, select};
+use log::{debug, info, warn};
 
 use crate::assets::ServerIcons;
+use crate::auth;
 use crate::codec::BincodeCodec;
+use crate::heartbeat::{Heartbeat, HeartbeatAction, HeartbeatConfig};
+use crate::lan_announcer::{self, lan_announcer, ShareMode};
+use crate::lan_listener::{self, lan_listener, DetectionMode};
 use crate::log_reader::{log_reader, ClientChange};
-use crate::messages::{ClientMessage, ServerMessage};
+use crate::messages::{APP_VERSION, ChatContent, ClientMessage, Handshake, LanGame, Member, PROTOCOL_VERSION, ServerMessage};
 use crate::nbt_editor::{nbt_editor, NbtInstruction};
+use crate::observer::{Observer, SessionEvent};
+use crate::outbox::QUEUE_SIZE;
+use crate::relay::{self, Links};
 use crate::sync::PauseToken;
-use crate::ui::delegate::RUNTIME_ERROR;
-use crate::ui::layouts::client::LAN_COUNT;
+use crate::timer::{self, ClockSync, TimerState};
+use crate::transport::{self, Stream, TlsError};
 
 const CONNECTION_TIMEOUT: f32 = 5.;
 
+/// First delay between reconnection attempts, in seconds.
+const INITIAL_BACKOFF: f32 = 0.5;
+
+/// Longest delay between reconnection attempts, in seconds.
+const MAX_BACKOFF: f32 = 30.;
+
+/// Latency change worth reporting to the hive, in seconds.
+const LATENCY_STEP: f32 = 0.02;
+
 /// Socket with bincode encoding and asymetric data.
 type EncodedSocket =
-    asynchronous_codec::Framed<TcpStream, BincodeCodec<ClientMessage, ServerMessage>>;
+    asynchronous_codec::Framed<Stream, BincodeCodec<ClientMessage, ServerMessage>>;
+
+/// Client startup configuration.
+pub struct ClientConfig {
+    pub icons: ServerIcons,
+    pub server_data_path: String,
+    pub log_path: String,
+    /// `host:port`, resolved on every connection attempt.
+    pub server_addr: String,
+    pub nickname: String,
+    /// Room to join on the hive, empty for the default one.
+    pub room: String,
+    /// Only used if the room has a password.
+    pub password: String,
+    /// Expects the hive to be encrypted.
+    pub tls: bool,
+    pub heartbeat: HeartbeatConfig,
+    /// Where opened and closed worlds are noticed.
+    pub detection: DetectionMode,
+    /// How hive games are shown in Minecraft.
+    pub sharing: ShareMode,
+    /// Key of the hive started by this host, if any.
+    pub host_key: Option<u64>,
+}
+
+/// Connection state reported to the observer.
+#[derive(Clone, Copy, Debug)]
+pub enum ConnectionStatus {
+    Connected,
+    /// Carries the number of failed attempts.
+    Reconnecting(u32),
+}
+
+/// Requests from the user interface.
+#[derive(Clone, Debug)]
+pub enum ClientCommand {
+    TimerStart,
+    TimerStop,
+    TimerReset,
+    Chat(ChatContent),
+}
+
+/// Session state that outlives a connection.
+struct Session {
+    /// Port of the hosted game, announced again after reconnecting.
+    hosting: Option<u16>,
+    clock: ClockSync,
+    timer: TimerState,
+    /// Relayed games are reached through the hive.
+    hive: IpAddr,
+    /// Relayed connections to the hosted game.
+    links: Links,
+    /// Game traffic on its way to the hive.
+    relay_sink: Sender<ClientMessage>,
+    relay_source: Receiver<ClientMessage>,
+    /// Searchers in the room, in joining order.
+    /// Hive sends the whole roster again on every connection.
+    roster: Vec<Member>,
+}
+
+/// What a session works with, for as long as it runs.
+struct SessionContext {
+    observer: Observer,
+    stop_token: Arc<PauseToken>,
+    pause_token: Arc<PauseToken>,
+    config: ClientConfig,
+    /// Hosting changes noticed in Minecraft.
+    log_source: Receiver<ClientChange>,
+    commands: Receiver<ClientCommand>,
+    nbt_instruction_send: Sender<NbtInstruction>,
+}
+
+/// Reasons for a failed connection attempt.
+enum ConnectError {
+    /// Retrying will not help.
+    Fatal(String),
+    /// Hive might come back.
+    Lost(String),
+    /// Hive address does not resolve, DNS might catch up.
+    Unresolved(String),
+}
+
+/// Reasons for the communication to end.
+enum SessionEnd {
+    Stopped,
+    Fatal(String),
+    Lost(String),
+}
 
+/// Connects to the server, fails right away if that is not possible.
+///
+/// Starts the client threads:
+///
+/// Session
+/// - relays log changes and commands to the server
+/// - relays server updates to the NBT editor
+/// - keeps the timer in sync
+/// - reconnects when the connection drops
+///
+/// Log reader and LAN listener, depending on the detection mode
+///
+/// NBT editor or LAN announcer, depending on the share mode
 pub fn start(
-    ui_event_sink: ExtEventSink,
+    observer: Observer,
     stop_token: Arc<PauseToken>,
     pause_token: Arc<PauseToken>,
-    icons: ServerIcons,
-    server_data_path: String,
-    log_path: String,
-    server_addr: SocketAddr,
+    config: ClientConfig,
+    commands: Receiver<ClientCommand>,
 ) {
-    let stream = block_on(timeout(
-        Duration::from_secs_f32(CONNECTION_TIMEOUT),
-        TcpStream::connect(server_addr),
-    ));
-    if let Ok(stream) = stream {
-        let stream: EncodedSocket = asynchronous_codec::Framed::new(stream, BincodeCodec::new());
-
-        let (log_sink, log_source) = unbounded::<ClientChange>();
-        let (nbt_instruction_send, nbt_instruction_recv) = unbounded::<NbtInstruction>();
-        let _stop_token = stop_token.clone();
-        let _pause_token = pause_token.clone();
-        spawn(communicate(
-            ui_event_sink,
-            _stop_token,
-            _pause_token,
-            log_source,
-            nbt_instruction_send,
-            stream,
+    let lan_socket = match config.detection.listens() {
+        true => match lan_listener::bind() {
+            Ok(socket) => Some(socket),
+            Err(error) if config.detection == DetectionMode::Multicast => {
+                observer.notify(SessionEvent::Failed(format!("Failed to listen for LAN worlds: {}", error)));
+                return
+            },
+            Err(error) => {
+                warn!("[client] failed to listen for LAN worlds, reading logs only: {}", error);
+                None
+            },
+        },
+        false => None,
+    };
+    let (stream, hive) = match block_on(connect(&config)) {
+        Ok(connected) => connected,
+        Err(ConnectError::Fatal(error)) | Err(ConnectError::Lost(error)) | Err(ConnectError::Unresolved(error)) => {
+            observer.notify(SessionEvent::Failed(error));
+            return
+        }
+    };
+
+    let (log_sink, log_source) = bounded::<ClientChange>(QUEUE_SIZE);
+    let (nbt_instruction_send, nbt_instruction_recv) = bounded::<NbtInstruction>(QUEUE_SIZE);
+    let icons = config.icons.clone();
+    let server_data_path = config.server_data_path.clone();
+    let log_path = config.log_path.clone();
+    let reads_logs = config.detection.reads_logs();
+    let sharing = config.sharing;
+    let context = SessionContext {
+        observer,
+        stop_token: stop_token.clone(),
+        pause_token: pause_token.clone(),
+        config,
+        log_source,
+        commands,
+        nbt_instruction_send,
+    };
+    spawn(run_session(context, stream, hive));
+
+    if let Some(socket) = lan_socket {
+        spawn(lan_listener(
+            stop_token.clone(),
+            pause_token.clone(),
+            socket,
+            lan_listener::local_addresses(),
+            log_sink.clone(),
         ));
+    }
 
-        let init_duration = Duration::from_secs(5);
-        let (_durations_send, durations_recv) = unbounded::<Duration>();
+    let init_duration = Duration::from_secs(5);
+    let (_durations_send, durations_recv) = unbounded::<Duration>();
+    if reads_logs {
         let _stop_token = stop_token.clone();
         let _pause_token = pause_token.clone();
         spawn(log_reader(
@@ -69,101 +216,476 @@ pub fn start(
             log_path,
             log_sink,
         ));
+    }
 
-        spawn(nbt_editor(
+    match sharing {
+        ShareMode::ServerList => spawn(nbt_editor(
             stop_token,
             pause_token,
             nbt_instruction_recv,
             icons,
             server_data_path,
-        ));
-        Box::leak(Box::new(_durations_send));
-    } else {
-        ui_event_sink
-            .submit_command(RUNTIME_ERROR, (), Target::Auto).ok();
+        )),
+        ShareMode::LanWorlds => spawn(lan_announcer(
+            stop_token,
+            pause_token,
+            nbt_instruction_recv,
+            lan_announcer::lan_group(),
+        )),
+    };
+    Box::leak(Box::new(_durations_send));
+}
+
+/// Whether the address has the `host:port` form, IPv6 addresses go in brackets.
+/// Says nothing about the host existing.
+pub fn is_valid_address(address: &str) -> bool {
+    if SocketAddr::from_str(address).is_ok() {
+        return true
+    }
+    match address.rsplit_once(':') {
+        Some((host, port)) => {
+            !host.is_empty()
+                && host.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '.')
+                && port.parse::<u16>().is_ok()
+        },
+        None => false,
     }
 }
 
-async fn communicate(
-    ui_event_sink: ExtEventSink,
-    stop_token: Arc<PauseToken>,
-    pause_token: Arc<PauseToken>,
-    log_source: Receiver<ClientChange>,
-    nbt_instruction_send: Sender<NbtInstruction>,
-    mut stream: EncodedSocket,
-) {
-    println!("[client] started");
-    stream.send(ClientMessage::Joined).await.ok();
+/// Resolves the hive address, all addresses of the host in the resolver's order.
+/// Returns a readable reason if there is nothing to connect to.
+pub async fn resolve(address: &str) -> Result<Vec<SocketAddr>, String> {
+    let resolved = future::timeout(Duration::from_secs_f32(CONNECTION_TIMEOUT), address.to_socket_addrs()).await;
+    match resolved {
+        Ok(Ok(addresses)) => {
+            let addresses: Vec<SocketAddr> = addresses.collect();
+            match addresses.is_empty() {
+                true => Err(format!("No addresses found for {}.", address)),
+                false => Ok(addresses),
+            }
+        },
+        Ok(Err(error)) => Err(format!("Failed to resolve {}: {}", address, error)),
+        Err(_) => Err(format!("Resolving {} took too long.", address)),
+    }
+}
+
+/// Connects, encrypts if required and introduces the client to the server.
+/// Tries every address of the hive, returns the one that answered.
+async fn connect(config: &ClientConfig) -> Result<(EncodedSocket, SocketAddr), ConnectError> {
+    let addresses = resolve(&config.server_addr).await.map_err(ConnectError::Unresolved)?;
+    let mut connected = None;
+    for address in addresses {
+        match timeout(Duration::from_secs_f32(CONNECTION_TIMEOUT), TcpStream::connect(address)).await {
+            Ok(stream) => {
+                connected = Some((stream, address));
+                break
+            },
+            Err(error) => debug!("[client] failed to reach {}: {}", address, error),
+        }
+    }
+    let (stream, address) = connected
+        .ok_or_else(|| ConnectError::Lost(format!("Failed to connect to {}.", config.server_addr)))?;
+    let stream = match config.tls {
+        true => transport::connect(stream, &config.server_addr)
+            .await
+            .map_err(|error| match error {
+                TlsError::FingerprintMismatch(_) => ConnectError::Fatal(error.to_string()),
+                TlsError::HandshakeFailed => ConnectError::Lost(error.to_string()),
+            })?,
+        false => transport::plain(stream),
+    };
+    let mut stream: EncodedSocket = asynchronous_codec::Framed::new(stream, BincodeCodec::new());
+    handshake(&mut stream, config.nickname.clone(), config.room.clone(), &config.password).await?;
+    if let Some(key) = config.host_key {
+        if stream.send(ClientMessage::HostKey(key)).await.is_err() {
+            return Err(ConnectError::Lost("Lost connection to the hive.".to_string()));
+        }
+    }
+    Ok((stream, address))
+}
+
+/// Introduces the client to the server and picks the room.
+/// Answers the password challenge if there is one.
+async fn handshake(stream: &mut EncodedSocket, nickname: String, room: String, password: &str) -> Result<(), ConnectError> {
+    let handshake = Handshake {
+        protocol_version: PROTOCOL_VERSION,
+        app_version: APP_VERSION.to_string(),
+        nickname,
+    };
+    if stream.send(ClientMessage::Handshake(handshake)).await.is_err() {
+        return Err(ConnectError::Lost("Lost connection to the hive.".to_string()));
+    }
+    if stream.send(ClientMessage::JoinRoom(room)).await.is_err() {
+        return Err(ConnectError::Lost("Lost connection to the hive.".to_string()));
+    }
+    loop {
+        let reply = future::timeout(Duration::from_secs_f32(CONNECTION_TIMEOUT), stream.try_next()).await;
+        match reply {
+            Ok(Ok(Some(ServerMessage::Accepted))) => return Ok(()),
+            Ok(Ok(Some(ServerMessage::Rejected(reason)))) => return Err(ConnectError::Fatal(reason.to_string())),
+            Ok(Ok(Some(ServerMessage::Challenge(challenge)))) => {
+                let proof = auth::prove(password, &challenge);
+                if stream.send(ClientMessage::ChallengeResponse(proof)).await.is_err() {
+                    return Err(ConnectError::Lost("Lost connection to the hive.".to_string()));
+                }
+            }
+            Ok(Ok(None)) => return Err(ConnectError::Lost("Hive closed the connection.".to_string())),
+            Ok(_) => return Err(ConnectError::Fatal("Hive replied with garbage, versions likely differ.".to_string())),
+            Err(_) => return Err(ConnectError::Lost("Hive did not respond.".to_string())),
+        }
+    }
+}
+
+/// Communicates with the server.
+/// Reconnects with exponential backoff when the connection drops.
+/// Announces the hosted game again after reconnecting.
+async fn run_session(context: SessionContext, mut stream: EncodedSocket, hive: SocketAddr) {
+    info!("[client] started");
+    let SessionContext { observer, stop_token, config, nbt_instruction_send, .. } = &context;
+    // Game traffic waits for the hive connection, instead of piling up.
+    let (relay_sink, relay_source) = bounded(QUEUE_SIZE);
+    let mut session = Session {
+        hosting: None,
+        clock: ClockSync::new(),
+        timer: TimerState::Reset,
+        hive: hive.ip(),
+        links: relay::links(),
+        relay_sink,
+        relay_source,
+        roster: Vec::new(),
+    };
+    loop {
+        let end = communicate(&context, &mut stream, &mut session).await;
+        match end {
+            SessionEnd::Stopped => break,
+            SessionEnd::Fatal(error) => {
+                observer.notify(SessionEvent::Failed(error));
+                break
+            }
+            SessionEnd::Lost(reason) => warn!("[client] {}", reason),
+        }
+        // Hive dropped the relayed connections along with this one.
+        session.links.lock().await.clear();
+        // Games may close while reconnecting, the hive sends them again once back.
+        observer.notify(SessionEvent::LanGames(Vec::new()));
+        nbt_instruction_send.send(NbtInstruction::SetToOffline).await.ok();
+        let (reconnected, hive) = match reconnect(observer, stop_token, config).await {
+            Some(connected) => connected,
+            None => break,
+        };
+        stream = reconnected;
+        // Hostnames might point elsewhere by now.
+        session.hive = hive.ip();
+        observer.notify(SessionEvent::Connection(ConnectionStatus::Connected));
+        if let Some(port) = session.hosting {
+            stream.send(ClientMessage::StartedHosting(port)).await.ok();
+        }
+    }
+    info!("[client] stopped");
+}
+
+/// Tries to connect until it succeeds, fails for good or gets stopped.
+async fn reconnect(
+    observer: &Observer,
+    stop_token: &Arc<PauseToken>,
+    config: &ClientConfig,
+) -> Option<(EncodedSocket, SocketAddr)> {
+    let mut backoff = Duration::from_secs_f32(INITIAL_BACKOFF);
+    let mut attempts = 0;
     while stop_token.is_paused().await {
+        observer.notify(SessionEvent::Connection(ConnectionStatus::Reconnecting(attempts)));
+        let delay = sleep(backoff).fuse();
+        let stop = stop_token.wait().fuse();
+        pin_mut!(delay);
+        pin_mut!(stop);
+        select! {
+            _ = delay => {},
+            _ = stop => break,
+        }
+        match connect(config).await {
+            Ok(connected) => {
+                info!("[client] reconnected");
+                return Some(connected)
+            }
+            Err(ConnectError::Fatal(error)) => {
+                observer.notify(SessionEvent::Failed(error));
+                break
+            }
+            Err(ConnectError::Lost(reason)) => {
+                warn!("[client] reconnection failed: {}", reason);
+                attempts += 1;
+                backoff = (backoff * 2).min(Duration::from_secs_f32(MAX_BACKOFF));
+            }
+            Err(ConnectError::Unresolved(reason)) => {
+                warn!("[client] cannot find the hive: {}", reason);
+                attempts += 1;
+                backoff = (backoff * 2).min(Duration::from_secs_f32(MAX_BACKOFF));
+            }
+        }
+    }
+    None
+}
+
+/// Relays messages until the connection drops or the client is stopped.
+/// Keeps track of the hosted game and the timer.
+async fn communicate(context: &SessionContext, stream: &mut EncodedSocket, session: &mut Session) -> SessionEnd {
+    let SessionContext { observer, stop_token, pause_token, config, log_source, commands, nbt_instruction_send } = context;
+    let mut heartbeat = Heartbeat::new(config.heartbeat);
+    // Latency the hive knows of, it forgets it along with the connection.
+    let mut reported = None;
+    stream.send(ClientMessage::TimeRequest(timer::now())).await.ok();
+    while stop_token.is_paused().await {
+        let relay_source = session.relay_source.clone();
         let server_message = stream.try_next().fuse();
         let client_change = log_source.recv().fuse();
+        let command = commands.recv().fuse();
+        let relayed = relay_source.recv().fuse();
+        let beat = sleep(heartbeat.until_next()).fuse();
         let stop = stop_token.wait().fuse();
         pin_mut!(server_message);
         pin_mut!(client_change);
+        pin_mut!(command);
+        pin_mut!(relayed);
+        pin_mut!(beat);
         pin_mut!(stop);
 
         select! {
             server_message = server_message => {
                 match server_message {
-                    Ok(opt_message) => {
-                        if let Some(message) = opt_message {
-                            from_server(&ui_event_sink, &nbt_instruction_send, message).await;
+                    Ok(Some(message)) => {
+                        heartbeat.seen();
+                        match message {
+                            ServerMessage::Ping(nonce) => {
+                                stream.send(ClientMessage::Pong(nonce)).await.ok();
+                            },
+                            ServerMessage::Pong(nonce) => {
+                                if let Some(latency) = heartbeat.pong(nonce) {
+                                    observer.notify(SessionEvent::Latency(latency));
+                                    if noticeable(reported, latency) {
+                                        reported = Some(latency);
+                                        stream.send(ClientMessage::Latency(latency)).await.ok();
+                                    }
+                                }
+                            },
+                            ServerMessage::TimeReply(sent, server) => {
+                                let changed = session.clock.sample(sent, server, timer::now());
+                                // Running timer drifts with the offset estimate.
+                                if changed && matches!(session.timer, TimerState::Running(_)) {
+                                    observer.notify(SessionEvent::Timer(session.clock.view(session.timer)));
+                                }
+                            },
+                            ServerMessage::ServerShutdown(reason) => {
+                                info!("[client] hive shut down: {}", reason);
+                                observer.notify(SessionEvent::LanGames(Vec::new()));
+                                nbt_instruction_send.send(NbtInstruction::SetToOffline).await.ok();
+                                // Hosts stopping their own hive need no explanation.
+                                if !stop_token.is_paused().await {
+                                    return SessionEnd::Stopped
+                                }
+                                return SessionEnd::Fatal(reason.to_string())
+                            },
+                            ServerMessage::Kicked(banned) => {
+                                let reason = match banned {
+                                    true => "You were banned from the hive.",
+                                    false => "You were kicked from the hive.",
+                                };
+                                return SessionEnd::Fatal(reason.to_string())
+                            },
+                            ServerMessage::Timer(state) => {
+                                session.timer = state;
+                                observer.notify(SessionEvent::Timer(session.clock.view(state)));
+                            },
+                            ServerMessage::RelayOpen(id) => {
+                                if !open_link(session, id).await {
+                                    stream.send(ClientMessage::RelayClose(id)).await.ok();
+                                }
+                            },
+                            ServerMessage::RelayData(id, data) => {
+                                relay::forward(&session.links, id, data).await;
+                            },
+                            ServerMessage::RelayClose(id) => {
+                                relay::unlink(&session.links, id).await;
+                            },
+                            ServerMessage::Chat(line) => {
+                                observer.notify(SessionEvent::Chat(line));
+                            },
+                            message @ ServerMessage::Roster(_)
+                            | message @ ServerMessage::MemberChanged(_)
+                            | message @ ServerMessage::MemberLeft(_) => {
+                                update_roster(&mut session.roster, message);
+                                observer.notify(SessionEvent::Roster(session.roster.clone()));
+                            },
+                            message => {
+                                from_server(observer, nbt_instruction_send, session.hive, message).await;
+                            },
                         }
                     },
-                    Err(_) => {
-                        println!("[client] socket disconnected");
-                        break
+                    Ok(None) | Err(_) => {
+                        return SessionEnd::Lost("socket disconnected".to_string())
                     }
                 }
             },
             client_change = client_change => {
                 if let Ok(client_change) = client_change {
-                    to_server(&mut stream, client_change).await;
+                    let hosting = match client_change {
+                        ClientChange::StartedHosting(port) => Some(port),
+                        ClientChange::StoppedHosting => None,
+                    };
+                    // Both detection sources report the same change.
+                    if session.hosting != hosting {
+                        session.hosting = hosting;
+                        to_server(stream, client_change).await;
+                    }
                 } else {
-                    println!("[client] log reader disconnected");
-                    ui_event_sink
-                        .submit_command(RUNTIME_ERROR, (), Target::Auto).ok();
-                    break
+                    warn!("[client] log reader disconnected");
+                    return SessionEnd::Fatal("Log reader stopped.".to_string())
+                }
+            },
+            command = command => {
+                if let Ok(command) = command {
+                    let message = match command {
+                        ClientCommand::TimerStart => ClientMessage::TimerStart(session.clock.server_now()),
+                        ClientCommand::TimerStop => ClientMessage::TimerStop(session.clock.server_now()),
+                        ClientCommand::TimerReset => ClientMessage::TimerReset,
+                        ClientCommand::Chat(content) => ClientMessage::Chat(content),
+                    };
+                    stream.send(message).await.ok();
+                } else {
+                    // Interface drops the feed right after stopping the client.
+                    if !stop_token.is_paused().await {
+                        return SessionEnd::Stopped
+                    }
+                    warn!("[client] command feed disconnected");
+                    return SessionEnd::Fatal("Command feed stopped.".to_string())
+                }
+            },
+            relayed = relayed => {
+                if let Ok(message) = relayed {
+                    stream.send(message).await.ok();
+                }
+            },
+            _ = beat => {
+                match heartbeat.poll() {
+                    Some(HeartbeatAction::Ping(nonce)) => {
+                        stream.send(ClientMessage::Ping(nonce)).await.ok();
+                        stream.send(ClientMessage::TimeRequest(timer::now())).await.ok();
+                    },
+                    Some(HeartbeatAction::TimedOut) => {
+                        return SessionEnd::Lost("hive timed out".to_string())
+                    },
+                    None => {},
                 }
             },
             _ = stop => {
-                println!("[client] stop requested");
+                info!("[client] stop requested");
                 break
             },
         }
 
         pause_token.wait().await;
     }
-    println!("[client] stopped");
+    SessionEnd::Stopped
+}
+
+/// Connects a player relayed by the hive to the hosted game.
+/// Returns `false` if there is no game to connect to, the hive has to be told.
+async fn open_link(session: &mut Session, id: u64) -> bool {
+    let game = match session.hosting {
+        Some(port) => SocketAddr::from((Ipv4Addr::LOCALHOST, port)),
+        None => return false,
+    };
+    match timeout(Duration::from_secs_f32(CONNECTION_TIMEOUT), TcpStream::connect(game)).await {
+        Ok(stream) => {
+            let inbox = relay::link(&session.links, id).await;
+            spawn(relay::pipe(
+                session.links.clone(),
+                id,
+                stream,
+                inbox,
+                session.relay_sink.clone(),
+                ClientMessage::RelayData,
+                ClientMessage::RelayClose,
+            ));
+            true
+        }
+        Err(error) => {
+            warn!("[client] failed to reach the hosted game: {}", error);
+            false
+        }
+    }
+}
+
+/// Whether the latency moved far enough from the reported one to tell the hive.
+fn noticeable(reported: Option<Duration>, latency: Duration) -> bool {
+    match reported {
+        Some(reported) => {
+            let change = latency.max(reported) - latency.min(reported);
+            change >= Duration::from_secs_f32(LATENCY_STEP)
+        }
+        None => true,
+    }
+}
+
+/// Applies a roster update, members stay in joining order.
+fn update_roster(roster: &mut Vec<Member>, message: ServerMessage) {
+    match message {
+        ServerMessage::Roster(members) => *roster = members,
+        ServerMessage::MemberChanged(member) => {
+            match roster.binary_search_by_key(&member.id, |known| known.id) {
+                Ok(index) => roster[index] = member,
+                Err(index) => roster.insert(index, member),
+            }
+        }
+        ServerMessage::MemberLeft(id) => roster.retain(|member| member.id != id),
+        _ => {}
+    }
 }
 
+/// Relays open games to the observer and Minecraft.
+/// Relayed games are pointed at the hive.
 async fn from_server(
-    ui_event_sink: &ExtEventSink,
+    observer: &Observer,
     nbt_instruction_send: &Sender<NbtInstruction>,
+    hive: IpAddr,
     server_message: ServerMessage,
 ) {
     match server_message {
+        // Only valid during the handshake.
+        ServerMessage::Accepted | ServerMessage::Rejected(_) | ServerMessage::Challenge(_) => None,
+        // Handled by the communication loop.
+        ServerMessage::Ping(_)
+        | ServerMessage::Pong(_)
+        | ServerMessage::TimeReply(_, _)
+        | ServerMessage::Timer(_)
+        | ServerMessage::Kicked(_)
+        | ServerMessage::ServerShutdown(_)
+        | ServerMessage::RelayOpen(_)
+        | ServerMessage::RelayData(_, _)
+        | ServerMessage::RelayClose(_)
+        | ServerMessage::Chat(_)
+        | ServerMessage::Roster(_)
+        | ServerMessage::MemberChanged(_)
+        | ServerMessage::MemberLeft(_) => None,
         ServerMessage::NoHost => {
-            ui_event_sink
-                .submit_command(LAN_COUNT, 0, Target::Auto).ok();
+            observer.notify(SessionEvent::LanGames(Vec::new()));
             nbt_instruction_send
                 .send(NbtInstruction::SetToNoHost)
                 .await
                 .ok()
         }
-        ServerMessage::OneHost(addr) => {
-            ui_event_sink
-                .submit_command(LAN_COUNT, 1, Target::Auto).ok();
+        ServerMessage::OneHost(game) => {
+            let game = game.resolve(hive);
+            observer.notify(SessionEvent::LanGames(vec![game.clone()]));
             nbt_instruction_send
-                .send(NbtInstruction::SetToOneHost(addr))
+                .send(NbtInstruction::SetToOneHost(game))
                 .await
                 .ok()
         }
-        ServerMessage::ManyHosts => {
-            ui_event_sink
-                .submit_command(LAN_COUNT, 2, Target::Auto).ok();
+        ServerMessage::ManyHosts(games) => {
+            let games: Vec<LanGame> = games.into_iter().map(|game| game.resolve(hive)).collect();
+            observer.notify(SessionEvent::LanGames(games.clone()));
             nbt_instruction_send
-                .send(NbtInstruction::SetToManyHosts)
+                .send(NbtInstruction::SetToManyHosts(games))
                 .await
                 .ok()
         }
@@ -0,0 +1,149 @@
+//! Hive discovery on the local network.
+//!
+//! Hosts broadcast a beacon over UDP every few seconds.
+//! Listeners collect the beacons and forget hives that went quiet.
+
+use std::collections::HashMap;
+use std::net::{Ipv4Addr, SocketAddr};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use async_std::future::timeout;
+use async_std::net::UdpSocket;
+use async_std::task::sleep;
+use futures::{FutureExt, pin_mut, select};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::messages::PROTOCOL_VERSION;
+use crate::observer::{Observer, SessionEvent};
+use crate::sync::PauseToken;
+
+/// Port the beacons are sent to.
+pub const DISCOVERY_PORT: u16 = 2138;
+
+/// Prefix of every beacon, anything else on the port is ignored.
+const MAGIC: &[u8] = b"HIVESRCH";
+
+/// Time between beacons, in seconds.
+const BEACON_INTERVAL: f32 = 2.;
+
+/// Silence after which a hive is forgotten, in seconds.
+const HIVE_EXPIRY: f32 = 7.;
+
+/// Broadcast by a hosting server.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Beacon {
+    pub protocol_version: u32,
+    pub name: String,
+    /// Port the hive accepts clients on.
+    pub port: u16,
+}
+
+/// Hive found on the local network.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Hive {
+    pub name: String,
+    pub address: SocketAddr,
+}
+
+/// Address beacons are broadcast to.
+pub fn broadcast_address() -> SocketAddr {
+    SocketAddr::from((Ipv4Addr::BROADCAST, DISCOVERY_PORT))
+}
+
+fn encode(beacon: &Beacon) -> Vec<u8> {
+    let mut packet = MAGIC.to_vec();
+    packet.extend(bincode::serialize(beacon).unwrap());
+    packet
+}
+
+fn decode(packet: &[u8]) -> Option<Beacon> {
+    if !packet.starts_with(MAGIC) {
+        return None
+    }
+    bincode::deserialize(&packet[MAGIC.len()..]).ok()
+}
+
+/// Sends the beacon to the target until stopped.
+pub async fn announce(stop_token: Arc<PauseToken>, beacon: Beacon, target: SocketAddr) {
+    let socket = match UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).await {
+        Ok(socket) => socket,
+        Err(error) => {
+            warn!("[discovery] failed to create the beacon socket: {}", error);
+            return
+        }
+    };
+    socket.set_broadcast(true).ok();
+    let packet = encode(&beacon);
+    info!("[discovery] announcing {} on port {}", beacon.name, beacon.port);
+    while stop_token.is_paused().await {
+        if let Err(error) = socket.send_to(&packet, target).await {
+            warn!("[discovery] failed to send the beacon: {}", error);
+        }
+        let delay = sleep(Duration::from_secs_f32(BEACON_INTERVAL)).fuse();
+        let stop = stop_token.wait().fuse();
+        pin_mut!(delay);
+        pin_mut!(stop);
+        select! {
+            _ = delay => {},
+            _ = stop => break,
+        }
+    }
+    info!("[discovery] stopped announcing");
+}
+
+/// Binds the socket beacons arrive at.
+pub async fn bind() -> std::io::Result<UdpSocket> {
+    UdpSocket::bind((Ipv4Addr::UNSPECIFIED, DISCOVERY_PORT)).await
+}
+
+/// Collects beacons until stopped.
+/// Reports the hives whenever one appears or goes quiet.
+/// Beacons of other protocol versions are ignored, those hives would refuse the client anyway.
+pub async fn listen(observer: Observer, stop_token: Arc<PauseToken>, socket: UdpSocket) {
+    let expiry = Duration::from_secs_f32(HIVE_EXPIRY);
+    let mut hives: HashMap<SocketAddr, (String, Instant)> = HashMap::new();
+    let mut buffer = [0u8; 512];
+    while stop_token.is_paused().await {
+        let received = {
+            let packet = timeout(expiry / 2, socket.recv_from(&mut buffer)).fuse();
+            let stop = stop_token.wait().fuse();
+            pin_mut!(packet);
+            pin_mut!(stop);
+            select! {
+                packet = packet => match packet {
+                    Ok(Ok(received)) => Some(received),
+                    Ok(Err(error)) => {
+                        warn!("[discovery] listener failed: {}", error);
+                        break
+                    },
+                    Err(_) => None,
+                },
+                _ = stop => break,
+            }
+        };
+        let mut changed = false;
+        if let Some((length, sender)) = received {
+            match decode(&buffer[..length]) {
+                Some(beacon) if beacon.protocol_version == PROTOCOL_VERSION => {
+                    let address = SocketAddr::new(sender.ip(), beacon.port);
+                    let previous = hives.insert(address, (beacon.name.clone(), Instant::now()));
+                    changed = !matches!(previous, Some((name, _)) if name == beacon.name);
+                },
+                _ => {},
+            }
+        }
+        let count = hives.len();
+        hives.retain(|_, (_, seen)| seen.elapsed() < expiry);
+        changed |= hives.len() != count;
+        if changed {
+            let mut found: Vec<Hive> = hives
+                .iter()
+                .map(|(address, (name, _))| Hive { name: name.clone(), address: *address })
+                .collect();
+            found.sort_by(|a, b| a.name.cmp(&b.name).then(a.address.cmp(&b.address)));
+            observer.notify(SessionEvent::Hives(found));
+        }
+    }
+}
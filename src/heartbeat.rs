@@ -0,0 +1,93 @@
+//! Keepalive for hive connections.
+//!
+//! Both sides ping each other regularly and answer pings with pongs.
+//! A peer that stays silent for longer than the timeout is considered dead.
+
+use std::time::{Duration, Instant};
+
+/// Heartbeat timing.
+#[derive(Clone, Copy, Debug)]
+pub struct HeartbeatConfig {
+    /// Time between pings.
+    pub interval: Duration,
+    /// Silence after which the peer is dropped.
+    pub timeout: Duration,
+}
+
+impl Default for HeartbeatConfig {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(2),
+            timeout: Duration::from_secs(10),
+        }
+    }
+}
+
+/// Actions requested by the heartbeat.
+pub enum HeartbeatAction {
+    /// Send a ping with the nonce.
+    Ping(u64),
+    /// Peer went silent, drop it.
+    TimedOut,
+}
+
+/// Tracks pings sent to a peer and anything received from it.
+pub struct Heartbeat {
+    config: HeartbeatConfig,
+    next_ping: Instant,
+    last_seen: Instant,
+    /// Nonce and send time of the last ping.
+    sent: Option<(u64, Instant)>,
+    nonce: u64,
+}
+
+impl Heartbeat {
+    pub fn new(config: HeartbeatConfig) -> Self {
+        let now = Instant::now();
+        Self {
+            config,
+            next_ping: now + config.interval,
+            last_seen: now,
+            sent: None,
+            nonce: 0,
+        }
+    }
+
+    /// Call on every message received from the peer.
+    pub fn seen(&mut self) {
+        self.last_seen = Instant::now();
+    }
+
+    /// Call on every pong received from the peer.
+    /// Returns the round-trip time if the pong answers the last ping.
+    pub fn pong(&mut self, nonce: u64) -> Option<Duration> {
+        match self.sent {
+            Some((sent_nonce, sent_at)) if sent_nonce == nonce => {
+                self.sent = None;
+                Some(sent_at.elapsed())
+            }
+            _ => None,
+        }
+    }
+
+    /// Time until the next action is due.
+    pub fn until_next(&self) -> Duration {
+        let deadline = self.next_ping.min(self.last_seen + self.config.timeout);
+        deadline.saturating_duration_since(Instant::now())
+    }
+
+    /// Returns the action that is due, if any.
+    pub fn poll(&mut self) -> Option<HeartbeatAction> {
+        let now = Instant::now();
+        if now >= self.last_seen + self.config.timeout {
+            return Some(HeartbeatAction::TimedOut);
+        }
+        if now >= self.next_ping {
+            self.next_ping = now + self.config.interval;
+            self.nonce += 1;
+            self.sent = Some((self.nonce, now));
+            return Some(HeartbeatAction::Ping(self.nonce));
+        }
+        None
+    }
+}
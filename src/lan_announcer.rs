@@ -0,0 +1,194 @@
+/*
+LAN announcer.
+Shows hive games among Minecraft's LAN worlds.
+*/
+
+use std::collections::HashMap;
+use std::net::{Ipv4Addr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_std::channel::Receiver;
+use async_std::io;
+use async_std::net::{Shutdown, TcpListener, TcpStream, UdpSocket};
+use async_std::task::{sleep, spawn};
+use futures::{FutureExt, pin_mut, select};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::lan_listener::{LAN_GROUP, LAN_PORT};
+use crate::messages::LanGame;
+use crate::nbt_editor::{NbtInstruction, MARKER};
+use crate::sync::PauseToken;
+
+/// Time between announcements, in seconds, same as Minecraft.
+const ANNOUNCE_INTERVAL: f32 = 1.5;
+
+/// Ways of showing hive games in Minecraft.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum ShareMode {
+    /// Marked entries in `servers.dat`, needs a refresh of the multiplayer screen.
+    ServerList,
+    /// Announced as LAN worlds, show up by themselves.
+    LanWorlds,
+}
+
+/// Address announcements are sent to.
+pub fn lan_group() -> SocketAddr {
+    SocketAddr::from((LAN_GROUP, LAN_PORT))
+}
+
+/// Hive game announced as a LAN world.
+/// Minecraft only takes the port from an announcement, so the game is reached through a local proxy.
+struct Proxy {
+    announcement: Vec<u8>,
+    stop_token: Arc<PauseToken>,
+}
+
+impl Proxy {
+    async fn open(game: &LanGame) -> std::io::Result<Self> {
+        let listener = TcpListener::bind((Ipv4Addr::UNSPECIFIED, 0)).await?;
+        let port = listener.local_addr()?.port();
+        let stop_token = Arc::new(PauseToken::new(true));
+        info!("[lan announcer] {}'s game proxied on port {}", game.nickname, port);
+        spawn(proxy(stop_token.clone(), listener, game.address));
+        Ok(Self {
+            announcement: format!("[MOTD]{}HiveSearch: {}'s Game[/MOTD][AD]{}[/AD]", MARKER, game.nickname, port).into_bytes(),
+            stop_token,
+        })
+    }
+
+    async fn close(self) {
+        self.stop_token.resume().await;
+    }
+}
+
+/// Forwards connections to the game until stopped.
+async fn proxy(stop_token: Arc<PauseToken>, listener: TcpListener, game: SocketAddr) {
+    while stop_token.is_paused().await {
+        let incoming = listener.accept().fuse();
+        let stop = stop_token.wait().fuse();
+        pin_mut!(incoming);
+        pin_mut!(stop);
+        select! {
+            incoming = incoming => match incoming {
+                Ok((stream, _)) => {
+                    spawn(forward(stream, game));
+                },
+                Err(error) => {
+                    warn!("[lan announcer] proxy failed: {}", error);
+                    break
+                },
+            },
+            _ = stop => break,
+        }
+    }
+}
+
+/// Copies data both ways until either side closes.
+async fn forward(inbound: TcpStream, game: SocketAddr) {
+    let outbound = match TcpStream::connect(game).await {
+        Ok(outbound) => outbound,
+        Err(error) => {
+            warn!("[lan announcer] failed to reach {}: {}", game, error);
+            return
+        }
+    };
+    let (mut inbound_reader, mut inbound_writer) = (inbound.clone(), inbound.clone());
+    let (mut outbound_reader, mut outbound_writer) = (outbound.clone(), outbound.clone());
+    let upstream = io::copy(&mut inbound_reader, &mut outbound_writer).fuse();
+    let downstream = io::copy(&mut outbound_reader, &mut inbound_writer).fuse();
+    pin_mut!(upstream);
+    pin_mut!(downstream);
+    select! {
+        _ = upstream => {},
+        _ = downstream => {},
+    }
+    inbound.shutdown(Shutdown::Both).ok();
+    outbound.shutdown(Shutdown::Both).ok();
+}
+
+/// Games listed by an instruction.
+fn games(instruction: NbtInstruction) -> Vec<LanGame> {
+    match instruction {
+        NbtInstruction::SetToNoHost | NbtInstruction::SetToOffline => Vec::new(),
+        NbtInstruction::SetToOneHost(game) => vec![game],
+        NbtInstruction::SetToManyHosts(games) => games,
+    }
+}
+
+/// Announces hive games to `target` until stopped.
+/// Takes the same instructions as the NBT editor.
+pub async fn lan_announcer(
+    stop_token: Arc<PauseToken>,
+    pause_token: Arc<PauseToken>,
+    instructions: Receiver<NbtInstruction>,
+    target: SocketAddr,
+) {
+    info!("[lan announcer] started");
+    let socket = match UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).await {
+        Ok(socket) => socket,
+        Err(error) => {
+            warn!("[lan announcer] failed to create the socket: {}", error);
+            return
+        }
+    };
+    // Announcements stay on this machine, the rest of the network can join the hive.
+    socket.set_multicast_ttl_v4(0).ok();
+    let mut proxies: HashMap<SocketAddr, Proxy> = HashMap::new();
+    while stop_token.is_paused().await {
+        for proxy in proxies.values() {
+            if let Err(error) = socket.send_to(&proxy.announcement, target).await {
+                warn!("[lan announcer] failed to announce: {}", error);
+            }
+        }
+
+        let instruction = instructions.recv().fuse();
+        let delay = sleep(Duration::from_secs_f32(ANNOUNCE_INTERVAL)).fuse();
+        let stop = stop_token.wait().fuse();
+        pin_mut!(instruction);
+        pin_mut!(delay);
+        pin_mut!(stop);
+        select! {
+            instruction = instruction => {
+                if let Ok(instruction) = instruction {
+                    let games = games(instruction);
+                    let closed: Vec<SocketAddr> = proxies
+                        .keys()
+                        .filter(|address| !games.iter().any(|game| game.address == **address))
+                        .cloned()
+                        .collect();
+                    for address in closed {
+                        if let Some(proxy) = proxies.remove(&address) {
+                            proxy.close().await;
+                        }
+                    }
+                    for game in games {
+                        if proxies.contains_key(&game.address) {
+                            continue
+                        }
+                        match Proxy::open(&game).await {
+                            Ok(proxy) => {
+                                proxies.insert(game.address, proxy);
+                            },
+                            Err(error) => warn!("[lan announcer] failed to open a proxy: {}", error),
+                        }
+                    }
+                } else {
+                    warn!("[lan announcer] client disconnected");
+                    break
+                }
+            },
+            _ = delay => {},
+            _ = stop => {
+                info!("[lan announcer] stop requested");
+                break
+            },
+        }
+        pause_token.wait().await;
+    }
+    for (_, proxy) in proxies.drain() {
+        proxy.close().await;
+    }
+    info!("[lan announcer] stopped");
+}
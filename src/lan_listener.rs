@@ -0,0 +1,158 @@
+/*
+LAN announcement listener.
+*/
+
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use async_std::channel::Sender;
+use async_std::future::timeout;
+use async_std::net::UdpSocket;
+use futures::{FutureExt, pin_mut, select};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use socket2::{Domain, Protocol, Socket, Type};
+
+use crate::log_reader::ClientChange;
+use crate::nbt_editor::MARKER;
+use crate::sync::PauseToken;
+
+/// Group Minecraft announces open LAN worlds to.
+pub const LAN_GROUP: Ipv4Addr = Ipv4Addr::new(224, 0, 2, 60);
+
+/// Port Minecraft announces open LAN worlds to.
+pub const LAN_PORT: u16 = 4445;
+
+/// Silence after which the world is considered closed, in seconds.
+/// Minecraft announces every 1.5 seconds.
+const WORLD_EXPIRY: f32 = 5.;
+
+/// Sources of hosting changes.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum DetectionMode {
+    /// Parses `latest.log`.
+    Logs,
+    /// Listens for Minecraft's LAN announcements.
+    Multicast,
+    /// Both of the above, whichever notices a change first.
+    Both,
+}
+
+impl DetectionMode {
+    pub fn reads_logs(self) -> bool {
+        self != DetectionMode::Multicast
+    }
+
+    pub fn listens(self) -> bool {
+        self != DetectionMode::Logs
+    }
+}
+
+/// Extracts the MOTD and the port from an announcement.
+/// `[MOTD]motd[/MOTD][AD]port[/AD]`, some versions send `host:port` instead.
+pub fn parse(packet: &str) -> Option<(String, u16)> {
+    let motd = between(packet, "[MOTD]", "[/MOTD]")?;
+    let ad = between(packet, "[AD]", "[/AD]")?;
+    let port = ad.rsplit(':').next()?.trim().parse().ok()?;
+    Some((motd.to_string(), port))
+}
+
+fn between<'a>(text: &'a str, open: &str, close: &str) -> Option<&'a str> {
+    let start = text.find(open)? + open.len();
+    let end = start + text[start..].find(close)?;
+    Some(&text[start..end])
+}
+
+/// Joins the announcement group.
+/// The port is shared, Minecraft binds it too while the multiplayer screen is open.
+pub fn bind() -> io::Result<UdpSocket> {
+    let socket = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP))?;
+    socket.set_reuse_address(true)?;
+    socket.bind(&SocketAddr::from((Ipv4Addr::UNSPECIFIED, LAN_PORT)).into())?;
+    socket.join_multicast_v4(&LAN_GROUP, &Ipv4Addr::UNSPECIFIED)?;
+    let socket: std::net::UdpSocket = socket.into();
+    Ok(UdpSocket::from(socket))
+}
+
+/// Addresses announcements of this machine come from.
+pub fn local_addresses() -> Vec<IpAddr> {
+    let mut addresses = vec![IpAddr::V4(Ipv4Addr::LOCALHOST)];
+    if let Ok(socket) = std::net::UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)) {
+        if socket.connect((LAN_GROUP, LAN_PORT)).is_ok() {
+            if let Ok(address) = socket.local_addr() {
+                addresses.push(address.ip());
+            }
+        }
+    }
+    addresses
+}
+
+/// Listens for LAN announcements of this machine.
+/// Signals about opened and closed worlds to `log_sink`, like the log reader.
+/// Announcements of other machines are ignored, those are not ours to share.
+/// So are hive games announced by this client.
+pub async fn lan_listener(
+    stop_token: Arc<PauseToken>,
+    pause_token: Arc<PauseToken>,
+    socket: UdpSocket,
+    local: Vec<IpAddr>,
+    log_sink: Sender<ClientChange>,
+) {
+    info!("[lan listener] started");
+    let expiry = Duration::from_secs_f32(WORLD_EXPIRY);
+    let mut hosting: Option<(u16, Instant)> = None;
+    let mut buffer = [0u8; 1024];
+    while stop_token.is_paused().await {
+        let received = {
+            let packet = timeout(expiry / 2, socket.recv_from(&mut buffer)).fuse();
+            let stop = stop_token.wait().fuse();
+            pin_mut!(packet);
+            pin_mut!(stop);
+            select! {
+                packet = packet => match packet {
+                    Ok(Ok(received)) => Some(received),
+                    Ok(Err(error)) => {
+                        warn!("[lan listener] socket failed: {}", error);
+                        break
+                    },
+                    Err(_) => None,
+                },
+                _ = stop => {
+                    info!("[lan listener] stop requested");
+                    break
+                },
+            }
+        };
+
+        let announced = received
+            .filter(|(_, sender)| local.contains(&sender.ip()))
+            .and_then(|(length, _)| parse(&String::from_utf8_lossy(&buffer[..length])))
+            .filter(|(motd, _)| !motd.starts_with(MARKER));
+        let change = match (announced, hosting) {
+            (Some((_, port)), Some((current, _))) if port == current => {
+                hosting = Some((port, Instant::now()));
+                None
+            },
+            (Some((_, port)), _) => {
+                hosting = Some((port, Instant::now()));
+                Some(ClientChange::StartedHosting(port))
+            },
+            (None, Some((_, seen))) if seen.elapsed() >= expiry => {
+                hosting = None;
+                Some(ClientChange::StoppedHosting)
+            },
+            (None, _) => None,
+        };
+        if let Some(change) = change {
+            if log_sink.send(change).await.is_err() {
+                warn!("[lan listener] client disconnected");
+                break
+            }
+        }
+
+        pause_token.wait().await;
+    }
+    info!("[lan listener] stopped");
+}
@@ -0,0 +1,23 @@
+//! HiveSearch networking core.
+//! Shared by the desktop application and the headless server.
+
+pub mod assets;
+pub mod auth;
+pub mod client;
+pub mod codec;
+pub mod discovery;
+pub mod heartbeat;
+pub mod lan_announcer;
+pub mod lan_listener;
+pub mod log_reader;
+pub mod messages;
+pub mod nbt_editor;
+pub mod observer;
+pub mod outbox;
+pub mod recording;
+pub mod relay;
+pub mod server;
+pub mod status;
+pub mod sync;
+pub mod timer;
+pub mod transport;
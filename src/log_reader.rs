@@ -4,6 +4,7 @@ Logs reader.
 
 use async_std::{channel::{Receiver, Sender}, task::sleep};
 use futures::{FutureExt, pin_mut, select};
+use log::{info, warn};
 use regex::RegexSet;
 use std::{fs::File, sync::Arc};
 use std::io::{BufRead, BufReader, Seek, SeekFrom};
@@ -35,7 +36,7 @@ pub async fn log_reader(
     log_path: String,
     log_sink: Sender<ClientChange>,
 ) {
-    println!("[log reader] started");
+    info!("[log reader] started");
     let mut memory = LogPollMemory::new();
     let matcher = RegexSet::new([STARTING, STOPPING]).unwrap();
     while stop_token.is_paused().await {
@@ -52,12 +53,12 @@ pub async fn log_reader(
                 if let Ok(new_duration) = result {
                     duration = new_duration;
                 } else {
-                    println!("[log reader] duration feed disconnected");
+                    warn!("[log reader] duration feed disconnected");
                     break
                 }
             },
             _ = stop => {
-                println!("[log reader] stop requested");
+                info!("[log reader] stop requested");
                 break
             },
         }
@@ -66,7 +67,7 @@ pub async fn log_reader(
 
         pause_token.wait().await;
     }
-    println!("[log reader] stopped");
+    info!("[log reader] stopped");
 }
 
 /// Stores data to detect updates in log files
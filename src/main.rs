@@ -1,16 +1,13 @@
-mod messages;
-mod client;
-mod server;
 mod ui;
-mod sync;
-mod assets;
-mod log_reader;
-mod nbt_editor;
-mod codec;
 mod resources;
 
+use std::sync::Arc;
+
+use async_std::task::spawn;
 use druid::*;
-use ui::{data::*, delegate::Delegate, main::hive, widgets::timer::TimerData};
+use hive_search::{discovery, sync::PauseToken};
+use log::warn;
+use ui::{data::*, delegate::Delegate, main::hive, observer::DruidObserver, widgets::timer::TimerData};
 
 fn main() {
     let hive_window = WindowDesc::new(hive())
@@ -22,7 +19,15 @@ fn main() {
         timer: TimerData::load(),
         ..Default::default()
     };
-    AppLauncher::with_window(hive_window)
+    let launcher = AppLauncher::with_window(hive_window);
+    let observer = Arc::new(DruidObserver::new(launcher.get_external_handle()));
+    spawn(async move {
+        match discovery::bind().await {
+            Ok(socket) => discovery::listen(observer, Arc::new(PauseToken::new(true)), socket).await,
+            Err(error) => warn!("[discovery] cannot listen for hives: {}", error),
+        }
+    });
+    launcher
         .delegate(Delegate)
         .log_to_console()
         .launch(data)
@@ -1,24 +1,263 @@
 //! Network communication standards.
 
 use serde::{Deserialize, Serialize};
-use std::net::SocketAddr;
+use crate::auth::{Challenge, Proof};
+use crate::timer::TimerState;
+use std::{fmt::{self, Display}, net::{IpAddr, SocketAddr}, time::{Duration, SystemTime}};
+
+/// Version of the message layout.
+/// Bump on every change to the messages below.
+pub const PROTOCOL_VERSION: u32 = 12;
+
+/// Version of the application.
+pub const APP_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Longest accepted nickname.
+pub const MAX_NICKNAME_LENGTH: usize = 32;
+
+/// Longest accepted room code.
+pub const MAX_ROOM_LENGTH: usize = 32;
+
+/// Longest accepted chat message.
+pub const MAX_CHAT_LENGTH: usize = 200;
+
+/// Introduction sent by a client right after connecting.
+///
+/// Field order must never change, so that any build can read the versions.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Handshake {
+    pub protocol_version: u32,
+    pub app_version: String,
+    pub nickname: String,
+}
+
+/// Reasons for the server to refuse a client.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum RejectReason {
+    /// Carries the protocol and application versions of the server.
+    ProtocolMismatch(u32, String),
+    InvalidNickname,
+    HandshakeExpected,
+    WrongPassword,
+    Banned,
+    InvalidRoom,
+}
+
+impl Display for RejectReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RejectReason::ProtocolMismatch(protocol_version, app_version) => write!(
+                f,
+                "Hive runs version {} (protocol {}), versions have to match.",
+                app_version, protocol_version
+            ),
+            RejectReason::InvalidNickname => write!(
+                f,
+                "Nickname can have at most {} characters and no '§'.",
+                MAX_NICKNAME_LENGTH
+            ),
+            RejectReason::InvalidRoom => write!(
+                f,
+                "Room code can have at most {} characters and no '§'.",
+                MAX_ROOM_LENGTH
+            ),
+            RejectReason::HandshakeExpected => {
+                write!(f, "Hive did not understand the handshake, versions likely differ.")
+            }
+            RejectReason::WrongPassword => write!(f, "Wrong room password."),
+            RejectReason::Banned => write!(f, "You are banned from this hive."),
+        }
+    }
+}
+
+/// Reasons for the hive to shut down.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub enum ShutdownReason {
+    /// Host stopped hosting.
+    Stopped,
+    /// Host application went away without stopping.
+    Abandoned,
+}
+
+impl Display for ShutdownReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ShutdownReason::Stopped => write!(f, "The host stopped the hive."),
+            ShutdownReason::Abandoned => write!(f, "The hive was closed."),
+        }
+    }
+}
+
+/// LAN game opened by one of the clients.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct LanGame {
+    /// Id of the hosting client.
+    pub id: u64,
+    pub nickname: String,
+    /// Unspecified IP if the game is relayed, it is reached through the hive then.
+    pub address: SocketAddr,
+    pub opened_at: SystemTime,
+}
+
+impl LanGame {
+    /// Points relayed games at the hive.
+    pub fn resolve(mut self, hive: IpAddr) -> Self {
+        if self.address.ip().is_unspecified() {
+            self.address.set_ip(hive);
+        }
+        self
+    }
+}
+
+/// Connected searcher, as listed in the roster.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Member {
+    pub id: u64,
+    pub nickname: String,
+    pub ip: IpAddr,
+    /// Server time of joining.
+    pub connected_since: SystemTime,
+    /// Port of the open LAN game, if any.
+    pub hosting: Option<u16>,
+    /// Round-trip time to the hive, as last reported by the searcher.
+    pub latency: Option<Duration>,
+    /// Runs the hive, cannot be kicked or banned.
+    pub host: bool,
+}
+
+/// Preset chat messages, one click away during a search.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub enum QuickPing {
+    FoundVillage,
+    FoundShipwreck,
+    FoundPortal,
+    FoundBastion,
+    FoundFortress,
+    FoundStronghold,
+    Resetting,
+}
+
+impl QuickPing {
+    /// Every ping, in the order they are offered.
+    pub const ALL: [QuickPing; 7] = [
+        QuickPing::FoundVillage,
+        QuickPing::FoundShipwreck,
+        QuickPing::FoundPortal,
+        QuickPing::FoundBastion,
+        QuickPing::FoundFortress,
+        QuickPing::FoundStronghold,
+        QuickPing::Resetting,
+    ];
+}
+
+impl Display for QuickPing {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let text = match self {
+            QuickPing::FoundVillage => "Found a village",
+            QuickPing::FoundShipwreck => "Found a shipwreck",
+            QuickPing::FoundPortal => "Found a ruined portal",
+            QuickPing::FoundBastion => "Found a bastion",
+            QuickPing::FoundFortress => "Found a fortress",
+            QuickPing::FoundStronghold => "Found the stronghold",
+            QuickPing::Resetting => "Resetting",
+        };
+        write!(f, "{}", text)
+    }
+}
+
+/// What a searcher said.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum ChatContent {
+    Text(String),
+    Ping(QuickPing),
+}
+
+impl Display for ChatContent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ChatContent::Text(text) => write!(f, "{}", text),
+            ChatContent::Ping(ping) => write!(f, "{}!", ping),
+        }
+    }
+}
+
+/// Chat message as spread by the server.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ChatLine {
+    pub nickname: String,
+    /// Server time of arrival.
+    pub sent_at: SystemTime,
+    pub content: ChatContent,
+}
 
 /// Messages generated by server for clients.
+///
+/// Handshake replies have to stay the first variants.
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub enum ServerMessage {
+    Accepted,
+    Rejected(RejectReason),
+    /// Sent after the handshake if the room has a password.
+    Challenge(Challenge),
     NoHost,
-    OneHost(SocketAddr),
-    ManyHosts,
-    //TimerStart,
-    //TimerStop,
+    OneHost(LanGame),
+    /// All open games, oldest first.
+    ManyHosts(Vec<LanGame>),
+    /// Heartbeat, has to be answered with a pong carrying the same nonce.
+    Ping(u64),
+    Pong(u64),
+    /// Answers a clock request with the sent time and the server time.
+    TimeReply(i64, i64),
+    Timer(TimerState),
+    /// Sent right before the server drops the client.
+    /// Carries whether the client got banned.
+    Kicked(bool),
+    /// Player connected to the relayed game, carries the connection id.
+    RelayOpen(u64),
+    /// Game traffic from the player.
+    RelayData(u64, Vec<u8>),
+    /// Player disconnected.
+    RelayClose(u64),
+    Chat(ChatLine),
+    /// Sent to everyone right before the hive stops.
+    ServerShutdown(ShutdownReason),
+    /// Everyone in the room, sent after joining.
+    Roster(Vec<Member>),
+    /// Someone joined the room, opened or closed a game, or reported a new latency.
+    MemberChanged(Member),
+    /// Someone left the room, carries the id.
+    MemberLeft(u64),
 }
 
 /// Messages generated by clients for server.
+///
+/// Handshake has to stay the first variant.
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub enum ClientMessage {
+    Handshake(Handshake),
+    ChallengeResponse(Proof),
     StartedHosting(u16),
     StoppedHosting,
-    Joined,
-    //TimerStart,
-    //TimerStop,
-}
\ No newline at end of file
+    /// Heartbeat, has to be answered with a pong carrying the same nonce.
+    Ping(u64),
+    Pong(u64),
+    /// Asks for the server time, carries the local send time.
+    TimeRequest(i64),
+    /// Carries the server time of the press.
+    TimerStart(i64),
+    /// Carries the server time of the press.
+    TimerStop(i64),
+    TimerReset,
+    /// Game traffic for the player.
+    RelayData(u64, Vec<u8>),
+    /// Game closed the connection.
+    RelayClose(u64),
+    Chat(ChatContent),
+    /// Round-trip time measured by the client, sent when it changes noticeably.
+    Latency(Duration),
+    /// Proves the client belongs to the host, carries the key the hive was started with.
+    HostKey(u64),
+    /// Room to join, empty for the default one.
+    /// Sent right after the handshake.
+    JoinRoom(String),
+}
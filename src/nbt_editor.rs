@@ -1,23 +1,26 @@
 use async_std::channel::Receiver;
 use futures::{FutureExt, pin_mut, select};
+use log::{info, warn};
 use nbt::*;
 use serde::{Deserialize, Serialize};
 use std::fs::{File, OpenOptions};
-use std::net::SocketAddr;
 use std::sync::Arc;
 use std::time::SystemTime;
 
 use crate::assets::ServerIcons;
+use crate::messages::LanGame;
 use crate::sync::PauseToken;
 
 /// Hive Search main address
-const MARKER: &str = "§5§2§7§d§8§2§a§e§r"; // 0x527D82AE
+pub const MARKER: &str = "§5§2§7§d§8§2§a§e§r"; // 0x527D82AE
 
 /// Possible NBT operations
 pub enum NbtInstruction {
     SetToNoHost,
-    SetToOneHost(SocketAddr),
-    SetToManyHosts,
+    SetToOneHost(LanGame),
+    SetToManyHosts(Vec<LanGame>),
+    /// Hive shut down, the entry stays until the next session.
+    SetToOffline,
 }
 
 /// Minecraft server representation
@@ -40,12 +43,6 @@ impl Server {
         }
     }
 
-    /// Change notable properties
-    fn update(&mut self, name: Option<String>, ip: Option<String>, icon: Option<String>) {
-        self.name = name;
-        self.ip = ip;
-        self.icon = icon;
-    }
 }
 
 /// Minecraft server list representation
@@ -55,20 +52,11 @@ struct ServerData {
 }
 
 /// Checks if a server has a marker
-fn has_marker<'a>(server: &'a mut Server, marker: &str) -> Option<&'a mut Server> {
+fn has_marker(server: &Server, marker: &str) -> bool {
     if let Some(name) = &server.name {
-        if name.starts_with(marker) {
-            return Some(server);
-        }
+        return name.starts_with(marker);
     }
-    None
-}
-
-/// Returns the marked server if it exists
-fn get_marked_server<'a>(servers: &'a mut Vec<Server>, marker: &str) -> Option<&'a mut Server> {
-    servers
-        .into_iter()
-        .find_map(|server| has_marker(server, marker))
+    false
 }
 
 /// Loads server list from a file
@@ -105,42 +93,73 @@ fn reload(
     data
 }
 
-/// Applies command to the server list
+/// Applies command to the server list.
+/// Marked servers are replaced in place, or appended if there were none.
 fn update_server_data(data: &mut ServerData, instruction: NbtInstruction, icons: &ServerIcons) {
-    let hive_search_server = get_marked_server(&mut data.servers, MARKER);
-    let (name, opt_ip, opt_icon) = data_from_instruction(instruction, icons);
-    if let Some(server) = hive_search_server {
-        server.update(Some(name), opt_ip, opt_icon);
-    } else {
-        data.servers.push(Server::new(Some(name), opt_ip, opt_icon));
-    }
+    let position = data
+        .servers
+        .iter()
+        .position(|server| has_marker(server, MARKER))
+        .unwrap_or(data.servers.len());
+    data.servers.retain(|server| !has_marker(server, MARKER));
+    let servers = servers_from_instruction(instruction, icons);
+    data.servers.splice(position..position, servers);
 }
 
-/// Applies instruction to a server
-fn data_from_instruction(
-    instruction: NbtInstruction,
-    icons: &ServerIcons,
-) -> (String, Option<String>, Option<String>) {
+/// Creates marked servers from an instruction
+fn servers_from_instruction(instruction: NbtInstruction, icons: &ServerIcons) -> Vec<Server> {
     match instruction {
-        NbtInstruction::SetToNoHost => (
-            format!("{}HiveSearch: §7No Games Open", MARKER),
+        NbtInstruction::SetToNoHost => vec![Server::new(
+            Some(format!("{}HiveSearch: §7No Games Open", MARKER)),
             None,
             icons.no_hosts.clone(),
-        ),
-        NbtInstruction::SetToOneHost(addr) => (
-            format!("{}HiveSearch: §aGame Open", MARKER),
-            Some(addr.to_string()),
+        )],
+        NbtInstruction::SetToOneHost(game) => vec![Server::new(
+            Some(format!("{}HiveSearch: §a{}'s Game", MARKER, game.nickname)),
+            Some(game.address.to_string()),
             None,
-        ),
-        NbtInstruction::SetToManyHosts => (
-            format!("{}HiveSearch: §6Multiple Games Open", MARKER),
+        )],
+        NbtInstruction::SetToManyHosts(games) => games
+            .into_iter()
+            .map(|game| {
+                Server::new(
+                    Some(format!("{}HiveSearch: §6{}'s Game", MARKER, game.nickname)),
+                    Some(game.address.to_string()),
+                    icons.many_hosts.clone(),
+                )
+            })
+            .collect(),
+        NbtInstruction::SetToOffline => vec![Server::new(
+            Some(format!("{}HiveSearch: §8Hive Offline", MARKER)),
             None,
-            icons.many_hosts.clone(),
-        ),
+            icons.no_hosts.clone(),
+        )],
     }
 }
 
+/// Applies the instruction to the server list, reloads it first if it was updated.
+/// Returns the new server list and its modification time.
+fn edit(
+    data: ServerData,
+    instruction: NbtInstruction,
+    icons: &ServerIcons,
+    server_data_path: &String,
+    last_modification: &SystemTime,
+) -> (ServerData, SystemTime) {
+    let mut data = reload(data, server_data_path, last_modification);
+    update_server_data(&mut data, instruction, icons);
+    save_data(server_data_path, &data);
+    let modification = File::open(server_data_path)
+        .unwrap()
+        .metadata()
+        .unwrap()
+        .modified()
+        .unwrap();
+    (data, modification)
+}
+
 /// Edits NBT based on incoming commands.
+/// Applies what is left once stopped, the client might have said the hive went offline.
 pub async fn nbt_editor(
     stop_token: Arc<PauseToken>,
     pause_token: Arc<PauseToken>,
@@ -148,7 +167,7 @@ pub async fn nbt_editor(
     icons: ServerIcons,
     server_data_path: String,
 ) {
-    println!("[nbt editor] started");
+    info!("[nbt editor] started");
     let mut last_modification: SystemTime = SystemTime::now();
     let mut data: ServerData = load_data(&server_data_path);
     while stop_token.is_paused().await {
@@ -160,26 +179,25 @@ pub async fn nbt_editor(
         select! {
             command = command => {
                 if let Ok(command) = command {
-                    data = reload(data, &server_data_path, &last_modification);
-                    update_server_data(&mut data, command, &icons);
-                    save_data(&server_data_path, &data);
-                    last_modification = File::open(&server_data_path)
-                        .unwrap()
-                        .metadata()
-                        .unwrap()
-                        .modified()
-                        .unwrap()
+                    let (edited, modification) = edit(data, command, &icons, &server_data_path, &last_modification);
+                    data = edited;
+                    last_modification = modification;
                 } else {
-                    println!("[nbt editor] client disconnected");
+                    warn!("[nbt editor] client disconnected");
                     break
                 }
             }
             _ = stop => {
-                println!("[nbt editor] stop requested");
+                info!("[nbt editor] stop requested");
                 break
             },
         }
         pause_token.wait().await;
     }
-    println!("[nbt editor] stopped");
+    while let Ok(command) = nbt_instruction_recv.try_recv() {
+        let (edited, modification) = edit(data, command, &icons, &server_data_path, &last_modification);
+        data = edited;
+        last_modification = modification;
+    }
+    info!("[nbt editor] stopped");
 }
@@ -0,0 +1,88 @@
+//! Session events for whoever drives the networking core.
+//!
+//! - Implement `SessionObserver` to react to events.
+//! - `ChannelObserver` forwards events into a channel, for headless use and tests.
+//! - `FanOutObserver` hands events to several observers.
+
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_std::channel::{unbounded, Receiver, Sender};
+
+use crate::client::ConnectionStatus;
+use crate::discovery::Hive;
+use crate::messages::{ChatLine, LanGame, Member};
+use crate::timer::TimerView;
+
+/// Notable changes of a hive session.
+#[derive(Clone, Debug)]
+pub enum SessionEvent {
+    /// Server accepts clients on the address.
+    Listening(SocketAddr),
+    /// Clients connected to the server, in joining order.
+    Members(Vec<Member>),
+    /// Client connected to the server opened or closed a game, or reported a new latency.
+    MemberChanged(Member),
+    /// Searchers in the room, as seen by the client, in joining order.
+    Roster(Vec<Member>),
+    /// Games open in the hive, as seen by the client.
+    LanGames(Vec<LanGame>),
+    /// Round-trip time between the client and the server.
+    Latency(Duration),
+    Connection(ConnectionStatus),
+    /// Hive timer on the local clock.
+    Timer(TimerView),
+    /// Hives announced on the local network.
+    Hives(Vec<Hive>),
+    /// Chat message, as received by the client.
+    Chat(ChatLine),
+    /// Session ended unexpectedly, carries a readable reason.
+    Failed(String),
+}
+
+/// Receives session events.
+pub trait SessionObserver: Send + Sync {
+    fn notify(&self, event: SessionEvent);
+}
+
+/// Shared observer handle.
+pub type Observer = Arc<dyn SessionObserver>;
+
+/// Forwards events into a channel.
+pub struct ChannelObserver {
+    sender: Sender<SessionEvent>,
+}
+
+impl ChannelObserver {
+    /// Creates the observer and the receiving end of its channel.
+    pub fn new() -> (Self, Receiver<SessionEvent>) {
+        let (sender, receiver) = unbounded();
+        (Self { sender }, receiver)
+    }
+}
+
+impl SessionObserver for ChannelObserver {
+    fn notify(&self, event: SessionEvent) {
+        self.sender.try_send(event).ok();
+    }
+}
+
+/// Hands every event to all of the observers, in order.
+pub struct FanOutObserver {
+    observers: Vec<Observer>,
+}
+
+impl FanOutObserver {
+    pub fn new(observers: Vec<Observer>) -> Self {
+        Self { observers }
+    }
+}
+
+impl SessionObserver for FanOutObserver {
+    fn notify(&self, event: SessionEvent) {
+        for observer in &self.observers {
+            observer.notify(event.clone());
+        }
+    }
+}
@@ -0,0 +1,155 @@
+//! Outgoing messages of a hive client.
+//!
+//! - Game list updates only matter in their latest version, a newer one replaces a queued one.
+//! - Everything else is queued, a client that lets the queue fill up stopped reading and is dropped.
+//! - Sending never waits, so a broadcast does not stall on one bad socket.
+
+use std::sync::{Arc, Mutex};
+
+use async_std::channel::{bounded, Receiver, Sender, TrySendError};
+use futures::{FutureExt, pin_mut, select_biased};
+
+use crate::messages::ServerMessage;
+
+/// Capacity of the channels between tasks.
+pub const QUEUE_SIZE: usize = 64;
+
+/// Messages queued for a client, not counting the game list.
+/// Leaves room for a burst from a crowded hive, e.g. everyone pinging at once.
+pub const OUTBOX_SIZE: usize = 1024;
+
+/// State shared by both ends.
+struct Shared {
+    /// Latest game list, not sent yet.
+    games: Mutex<Option<ServerMessage>>,
+    /// Closed once the client fell behind, nothing is ever sent through it.
+    dropped: (Sender<()>, Receiver<()>),
+}
+
+/// Sending end, owned by the state manager.
+/// Dropping it lets the client handler send what is queued and stop.
+pub struct Outbox {
+    queue: Sender<ServerMessage>,
+    wake: Sender<()>,
+    shared: Arc<Shared>,
+}
+
+/// Receiving end, owned by the client handler.
+pub struct Inbox {
+    queue: Receiver<ServerMessage>,
+    wake: Receiver<()>,
+    shared: Arc<Shared>,
+}
+
+/// Creates both ends, queueing at most `capacity` messages besides the game list.
+pub fn outbox(capacity: usize) -> (Outbox, Inbox) {
+    let (queue_send, queue_recv) = bounded(capacity);
+    let (wake_send, wake_recv) = bounded(1);
+    let shared = Arc::new(Shared {
+        games: Mutex::new(None),
+        dropped: bounded(1),
+    });
+    let outbox = Outbox {
+        queue: queue_send,
+        wake: wake_send,
+        shared: shared.clone(),
+    };
+    let inbox = Inbox {
+        queue: queue_recv,
+        wake: wake_recv,
+        shared,
+    };
+    (outbox, inbox)
+}
+
+impl Shared {
+    fn is_dropped(&self) -> bool {
+        self.dropped.0.is_closed()
+    }
+}
+
+impl Outbox {
+    /// Hands the message over without waiting.
+    /// Drops the client if its queue is full.
+    /// Returns whether the message will be sent.
+    pub fn send(&self, message: ServerMessage) -> bool {
+        if self.shared.is_dropped() {
+            return false
+        }
+        match message {
+            ServerMessage::NoHost | ServerMessage::OneHost(_) | ServerMessage::ManyHosts(_) => {
+                *self.shared.games.lock().unwrap() = Some(message);
+                self.wake.try_send(()).ok();
+                true
+            },
+            message => match self.queue.try_send(message) {
+                Ok(()) => true,
+                Err(TrySendError::Full(_)) => {
+                    self.shared.dropped.0.close();
+                    false
+                },
+                Err(TrySendError::Closed(_)) => false,
+            },
+        }
+    }
+
+    /// Whether the client fell behind and got dropped.
+    pub fn is_dropped(&self) -> bool {
+        self.shared.is_dropped()
+    }
+}
+
+impl Inbox {
+    /// Next message to send, the game list first.
+    /// Returns `None` once the outbox is gone and the queue is empty, or right away if the client was dropped.
+    pub async fn recv(&self) -> Option<ServerMessage> {
+        loop {
+            if let Some(message) = self.try_games() {
+                return Some(message)
+            }
+            let queued = self.queue.recv().fuse();
+            let woken = self.wake.recv().fuse();
+            let dropped = self.dropped().fuse();
+            pin_mut!(queued);
+            pin_mut!(woken);
+            pin_mut!(dropped);
+            // The game list goes before anything queued with it.
+            select_biased! {
+                _ = dropped => return None,
+                woken = woken => {
+                    if woken.is_err() {
+                        // No more updates, only what is left.
+                        return match self.try_games() {
+                            Some(message) => Some(message),
+                            None => self.queue.recv().await.ok().filter(|_| !self.is_dropped()),
+                        }
+                    }
+                },
+                message = queued => return message.ok().filter(|_| !self.is_dropped()),
+            }
+        }
+    }
+
+    /// Next message if there is one, the game list first.
+    pub fn try_recv(&self) -> Option<ServerMessage> {
+        self.try_games().or_else(|| self.queue.try_recv().ok())
+    }
+
+    fn try_games(&self) -> Option<ServerMessage> {
+        if self.is_dropped() {
+            return None
+        }
+        self.shared.games.lock().unwrap().take()
+    }
+
+    /// Whether the client fell behind and got dropped.
+    pub fn is_dropped(&self) -> bool {
+        self.shared.is_dropped()
+    }
+
+    /// Completes once the client falls behind.
+    /// Lets the handler give up on a write that would never finish.
+    pub async fn dropped(&self) {
+        self.shared.dropped.1.recv().await.ok();
+    }
+}
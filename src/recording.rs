@@ -0,0 +1,84 @@
+//! Session recordings.
+//!
+//! The server can append everything that changes its state to a file.
+//! Replaying the file shows who opened what and when, and what everyone was told.
+
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::net::IpAddr;
+use std::path::Path;
+use std::time::SystemTime;
+
+use serde::{Deserialize, Serialize};
+
+use crate::messages::{ClientMessage, ServerMessage};
+use crate::server::{HostPolicy, ServerCommand};
+
+/// Input of the server state.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub enum StateEvent {
+    /// Server started, carries the protocol version.
+    /// Client ids start over.
+    Started(u32),
+    /// Policy the server started with, follows `Started`.
+    Policy(HostPolicy),
+    /// Carries the id, nickname, IP and room.
+    Joined(u64, String, IpAddr, String),
+    Left(u64),
+    Message(u64, ClientMessage),
+    Command(ServerCommand),
+    /// Empty room was closed, carries its code.
+    Expired(String),
+}
+
+/// Recorded event.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Record {
+    pub at: SystemTime,
+    pub event: StateEvent,
+}
+
+/// Replayed event and the messages it caused, with recipient ids.
+#[derive(Clone, Debug)]
+pub struct Replayed {
+    pub record: Record,
+    pub sent: Vec<(u64, ServerMessage)>,
+}
+
+/// Appends records to a file.
+pub struct Recorder {
+    file: BufWriter<File>,
+}
+
+impl Recorder {
+    /// Opens the recording, creates it if missing.
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self {
+            file: BufWriter::new(file),
+        })
+    }
+
+    /// Writes the record right away, so a crash loses nothing.
+    pub fn record(&mut self, at: SystemTime, event: StateEvent) -> io::Result<()> {
+        bincode::serialize_into(&mut self.file, &Record { at, event })
+            .map_err(|error| io::Error::new(io::ErrorKind::Other, error))?;
+        self.file.flush()
+    }
+}
+
+/// Reads all records of a file.
+pub fn load(path: &Path) -> io::Result<Vec<Record>> {
+    let mut file = BufReader::new(File::open(path)?);
+    let mut records = Vec::new();
+    while !file.fill_buf()?.is_empty() {
+        let record = bincode::deserialize_from(&mut file).map_err(|error| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("record {} is unreadable, the recording might be from another version: {}", records.len(), error),
+            )
+        })?;
+        records.push(record);
+    }
+    Ok(records)
+}
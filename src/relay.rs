@@ -0,0 +1,169 @@
+//! Game traffic relayed through the hive connection.
+//!
+//! Hosts behind NAT cannot be reached by other searchers.
+//! The server opens a public port for such a game instead and every connection to it
+//! is carried to the host client as messages, which hands it to the local Minecraft.
+
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use async_std::channel::{bounded, Receiver, Sender, TrySendError};
+use async_std::io::{ReadExt, WriteExt};
+use async_std::net::{Shutdown, TcpListener, TcpStream};
+use async_std::sync::Mutex;
+use async_std::task::spawn;
+use futures::{FutureExt, pin_mut, select};
+use log::{debug, info, warn};
+
+use crate::messages::ServerMessage;
+use crate::outbox::QUEUE_SIZE;
+use crate::sync::PauseToken;
+use crate::transport;
+
+/// Largest chunk of game traffic carried by a single message.
+const CHUNK_SIZE: usize = 16 * 1024;
+
+/// Source of connection ids, unique for the whole hive.
+/// Stale messages of a closed relay cannot hit a newer connection then.
+static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+
+/// Open relayed connections, by id.
+/// Each link feeds the writing half of a connection.
+pub type Links = Arc<Mutex<HashMap<u64, Sender<Vec<u8>>>>>;
+
+/// Creates an empty set of links.
+pub fn links() -> Links {
+    Arc::new(Mutex::new(HashMap::new()))
+}
+
+/// Registers a connection, returns what has to be written to it.
+pub async fn link(links: &Links, id: u64) -> Receiver<Vec<u8>> {
+    let (sender, receiver) = bounded(QUEUE_SIZE);
+    links.lock().await.insert(id, sender);
+    receiver
+}
+
+/// Hands data to a connection.
+/// Unknown connections were already closed, the data is dropped.
+/// Never waits, a connection that falls behind is closed instead.
+pub async fn forward(links: &Links, id: u64, data: Vec<u8>) {
+    let mut links = links.lock().await;
+    if let Some(link) = links.get(&id) {
+        if let Err(TrySendError::Full(_)) = link.try_send(data) {
+            warn!("[relay] connection {} fell behind, closed", id);
+            links.remove(&id);
+        }
+    }
+}
+
+/// Closes a connection from the other end.
+pub async fn unlink(links: &Links, id: u64) {
+    links.lock().await.remove(&id);
+}
+
+/// Carries a connection until either end closes it.
+/// Read data is wrapped with `data` and sent to `outbox`, `close` announces the end.
+pub async fn pipe<T>(
+    links: Links,
+    id: u64,
+    stream: TcpStream,
+    inbox: Receiver<Vec<u8>>,
+    outbox: Sender<T>,
+    data: fn(u64, Vec<u8>) -> T,
+    close: fn(u64) -> T,
+) {
+    let mut reader = stream.clone();
+    let mut writer = stream.clone();
+    let read = async {
+        let mut buffer = vec![0u8; CHUNK_SIZE];
+        loop {
+            match reader.read(&mut buffer).await {
+                Ok(0) | Err(_) => break,
+                Ok(length) => {
+                    if outbox.send(data(id, buffer[..length].to_vec())).await.is_err() {
+                        break
+                    }
+                }
+            }
+        }
+    }
+    .fuse();
+    let write = async {
+        while let Ok(chunk) = inbox.recv().await {
+            if writer.write_all(&chunk).await.is_err() {
+                break
+            }
+        }
+    }
+    .fuse();
+    pin_mut!(read);
+    pin_mut!(write);
+    select! {
+        _ = read => {},
+        _ = write => {},
+    }
+    stream.shutdown(Shutdown::Both).ok();
+    links.lock().await.remove(&id);
+    outbox.send(close(id)).await.ok();
+    debug!("[relay] connection {} closed", id);
+}
+
+/// Opens a public port for a game, returns its address.
+/// Connections are announced to the host through `host` until stopped.
+pub async fn open(
+    stop_token: Arc<PauseToken>,
+    address: SocketAddr,
+    host: Sender<ServerMessage>,
+    links: Links,
+) -> std::io::Result<SocketAddr> {
+    let listener = transport::listen(address)?;
+    let address = listener.local_addr()?;
+    info!("[relay] opened {}", address);
+    spawn(accept(stop_token, listener, host, links));
+    Ok(address)
+}
+
+/// Accepts players until stopped, then drops all their connections.
+async fn accept(
+    stop_token: Arc<PauseToken>,
+    listener: TcpListener,
+    host: Sender<ServerMessage>,
+    links: Links,
+) {
+    while stop_token.is_paused().await {
+        let incoming = listener.accept().fuse();
+        let stop = stop_token.wait().fuse();
+        pin_mut!(incoming);
+        pin_mut!(stop);
+        select! {
+            incoming = incoming => match incoming {
+                Ok((stream, player)) => {
+                    let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+                    debug!("[relay] {} connected as {}", player, id);
+                    let inbox = link(&links, id).await;
+                    if host.send(ServerMessage::RelayOpen(id)).await.is_err() {
+                        break
+                    }
+                    spawn(pipe(
+                        links.clone(),
+                        id,
+                        stream,
+                        inbox,
+                        host.clone(),
+                        ServerMessage::RelayData,
+                        ServerMessage::RelayClose,
+                    ));
+                },
+                Err(error) => {
+                    warn!("[relay] failed to accept: {}", error);
+                    break
+                },
+            },
+            _ = stop => break,
+        }
+    }
+    links.lock().await.clear();
+    info!("[relay] closed {}", listener.local_addr().map(|address| address.to_string()).unwrap_or_default());
+}
@@ -2,17 +2,25 @@
 //! Manages internal state and communication with clients.
 //!
 //! - Start with the `start` function.
-//! - Control (stop/pause) with tokens.
-//! - Gather response through event sink.
+//! - Control (stop/pause) with tokens, clients are told when the server stops.
+//! - Kick and ban clients with commands.
+//! - Optionally record state changes, `replay` plays them back.
+//! - Optionally relay games of hosts that cannot be reached directly.
+//! - Policy decides which games are shown when several are open.
+//! - Clients join rooms, rooms share nothing but the socket and expire once empty.
+//! - Slow clients are dropped rather than buffered for, see `outbox`.
+//! - Gather response through the observer.
 //! - The server is setup on the provided address.
 
-use std::{collections::HashMap, net::IpAddr};
+use std::{collections::{HashMap, HashSet}, net::{IpAddr, Ipv4Addr}, path::PathBuf, time::{Duration, SystemTime}};
 
-use async_std::{channel::{unbounded, Receiver, Sender}, net::{SocketAddr, TcpListener, TcpStream}, sync::Arc, task::spawn};
-use druid::{ExtEventSink, Target};
+use async_std::{channel::{bounded, Receiver, Sender}, future::timeout, net::{SocketAddr, TcpStream}, sync::{Arc, Mutex}, task::{sleep, spawn}};
+use futures_rustls::TlsAcceptor;
+use log::{debug, error, info, warn};
+use serde::{Deserialize, Serialize};
 use futures::*;
 
-use crate::{codec::BincodeCodec, messages::{ClientMessage, ServerMessage}, sync::PauseToken, ui::{delegate::RUNTIME_ERROR, layouts::host::USER_COUNT}};
+use crate::{auth, codec::BincodeCodec, discovery::{self, Beacon}, heartbeat::{Heartbeat, HeartbeatAction, HeartbeatConfig}, observer::{ChannelObserver, Observer, SessionEvent}, outbox::{self, Inbox, Outbox, OUTBOX_SIZE, QUEUE_SIZE}, recording::{Record, Recorder, Replayed, StateEvent}, relay::{self, Links}, transport::{self, Stream}, messages::{APP_VERSION, ChatContent, ChatLine, ClientMessage, Handshake, LanGame, MAX_CHAT_LENGTH, MAX_NICKNAME_LENGTH, MAX_ROOM_LENGTH, Member, PROTOCOL_VERSION, RejectReason, ServerMessage, ShutdownReason}, sync::PauseToken, timer::{self, TimerState}};
 
 /// Starts the server threads:
 ///
@@ -25,58 +33,184 @@ use crate::{codec::BincodeCodec, messages::{ClientMessage, ServerMessage}, sync:
 /// - sends updates to clients
 /// - removes disconnected clients
 pub fn start(
-    ui_event_sink: ExtEventSink,
+    observer: Observer,
     stop_token: Arc<PauseToken>,
     pause_token: Arc<PauseToken>,
-    server_address: SocketAddr,
+    config: ServerConfig,
+    commands: Receiver<ServerCommand>,
 ) {
-    let (new_client_ios_sender, new_client_ios_receiver) = unbounded();
+    let (new_client_ios_sender, new_client_ios_receiver) = bounded(QUEUE_SIZE);
+    let config = Arc::new(config);
+    let bans: Bans = Arc::new(Mutex::new(HashSet::new()));
 
-    let _ui_event_sink = ui_event_sink.clone();
+    let _observer = observer.clone();
     let _pause_token = pause_token.clone();
     let _stop_token = stop_token.clone();
+    let _bans = bans.clone();
 
     spawn(client_connections_receiver(
-        _ui_event_sink,
+        _observer,
         _stop_token,
         _pause_token,
         new_client_ios_sender,
-        server_address,
+        config.clone(),
+        _bans,
     ));
 
     spawn(server_state_manager(
-        ui_event_sink,
+        observer,
         stop_token,
         pause_token,
         new_client_ios_receiver,
+        commands,
+        bans,
+        config,
     ));
 }
 
+/// Requests from the host.
+/// Server stops when the sender is dropped.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+pub enum ServerCommand {
+    Kick(u64),
+    /// Kicks the client and everyone else from its IP, refuses the IP from then on.
+    Ban(u64),
+}
+
+/// Which games are shown when several searchers open one.
+/// Except for `ShowAll` a single game is shown, the rest wait until it closes.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum HostPolicy {
+    FirstOpened,
+    MostRecent,
+    /// Games of earlier nicknames win, unlisted nicknames come last.
+    /// Ties go to the first opened game.
+    Priority(Vec<String>),
+    ShowAll,
+}
+
+/// Server startup configuration.
+pub struct ServerConfig {
+    pub address: SocketAddr,
+    /// Clients have to prove they know it, if set.
+    pub password: Option<String>,
+    /// Encrypts connections with a self-signed certificate.
+    pub tls: bool,
+    pub heartbeat: HeartbeatConfig,
+    /// File to append state changes to, for later replay.
+    pub recording: Option<PathBuf>,
+    /// Room name announced on the local network, the hive stays unlisted if not set.
+    pub discovery: Option<String>,
+    /// Opens a port on the hive for every game and relays it to the host.
+    /// Hosts do not have to forward ports then.
+    pub relay: bool,
+    pub policy: HostPolicy,
+    /// Time an empty room keeps its games and timer.
+    pub room_expiry: Duration,
+    /// Known only to the host's own client, which cannot be kicked or banned then.
+    pub host_key: Option<u64>,
+}
+
+/// Default time an empty room is kept, in seconds.
+pub const ROOM_EXPIRY: f32 = 600.;
+
+/// Time for a new client to introduce itself, in seconds.
+const HANDSHAKE_TIMEOUT: f32 = 5.;
+
 /// Client communication interface.
 struct ClientIO {
-    to: Sender<ServerMessage>,
-    from: Receiver<ClientMessage>,
+    to: Outbox,
+    /// Relayed game traffic, waits for the client rather than dropping it.
+    relayed: Sender<ServerMessage>,
     ip: IpAddr,
+    nickname: String,
+    room: String,
+    /// Relayed connections to the game of this client.
+    links: Links,
+    /// Set when the client enters its room.
+    connected_since: SystemTime,
+    /// Port of the open game.
+    hosting: Option<u16>,
+    /// Last round-trip time reported by the client.
+    latency: Option<Duration>,
+    /// Proved it belongs to the host.
+    host: bool,
+}
+
+impl ClientIO {
+    /// Roster entry of the client.
+    fn member(&self, id: u64) -> Member {
+        Member {
+            id,
+            nickname: self.nickname.clone(),
+            ip: self.ip,
+            connected_since: self.connected_since,
+            hosting: self.hosting,
+            latency: self.latency,
+            host: self.host,
+        }
+    }
 }
 
 /// Map of all connected clients.
 type ClientIOs = HashMap<u64, ClientIO>;
 
+/// Client joining the state manager, along with its messages.
+type NewClient = (ClientIO, Receiver<ClientMessage>);
+
+/// Ends of a client handler towards the rest of the server.
+struct ClientChannels {
+    to_server: Sender<ClientMessage>,
+    from_server: Inbox,
+    from_relays: Receiver<ServerMessage>,
+    /// Relayed connections of the client, fed without the state manager.
+    links: Links,
+}
+
+/// Messages of every client tagged with its id, `None` once it disconnects.
+/// Only clients with something to say get polled.
+type ClientEvents = stream::SelectAll<stream::BoxStream<'static, (u64, Option<ClientMessage>)>>;
+
+/// Banned IPs, shared by the socket and the state manager.
+type Bans = Arc<Mutex<HashSet<IpAddr>>>;
+
 /// Socket with bincode encoding and asymetric data.
 type EncodedSocket =
-    asynchronous_codec::Framed<TcpStream, BincodeCodec<ServerMessage, ClientMessage>>;
+    asynchronous_codec::Framed<Stream, BincodeCodec<ServerMessage, ClientMessage>>;
 
 /// Awaits for incoming client connections
 /// Setups further communication
 async fn client_connections_receiver(
-    ui_event_sink: ExtEventSink,
+    observer: Observer,
     stop_token: Arc<PauseToken>,
     pause_token: Arc<PauseToken>,
-    new_client_ios: Sender<ClientIO>,
-    server_address: SocketAddr,
+    new_client_ios: Sender<NewClient>,
+    config: Arc<ServerConfig>,
+    bans: Bans,
 ) {
-    if let Ok(listener) = TcpListener::bind(server_address).await {
-        println!("[server socket] started");
+    let acceptor = match config.tls {
+        true => match transport::acceptor() {
+            Ok(acceptor) => Some(acceptor),
+            Err(error) => {
+                error!("[server socket] failed to load certificate: {}", error);
+                observer.notify(SessionEvent::Failed(format!("Failed to load the hive certificate: {}", error)));
+                return
+            }
+        },
+        false => None,
+    };
+    if let Ok(listener) = transport::listen(config.address) {
+        let address = listener.local_addr().unwrap_or(config.address);
+        info!("[server socket] started on {}", address);
+        observer.notify(SessionEvent::Listening(address));
+        if let Some(name) = &config.discovery {
+            let beacon = Beacon {
+                protocol_version: PROTOCOL_VERSION,
+                name: name.clone(),
+                port: address.port(),
+            };
+            spawn(discovery::announce(stop_token.clone(), beacon, discovery::broadcast_address()));
+        }
         while stop_token.is_paused().await {
             let incoming_connection = listener.accept().fuse();
             let stop = stop_token.wait().fuse();
@@ -85,103 +219,340 @@ async fn client_connections_receiver(
             select! {
                 incoming_connection = incoming_connection => {
                     if let Ok((stream, client_address)) = incoming_connection {
-                        let stream: EncodedSocket = asynchronous_codec::Framed::new(stream, BincodeCodec::new());
-                        let (to_server, from_client) = unbounded::<ClientMessage>();
-                        let (to_client, from_server) = unbounded::<ServerMessage>();
-                        let client_io = ClientIO{to: to_client, from: from_client, ip: client_address.ip()};
-                        if let Err(_) = new_client_ios.send(client_io).await {
-                            println!("[server socket] state manager disconnected");
-                            break
+                        let client_address = transport::unmapped(client_address);
+                        if bans.lock().await.contains(&client_address.ip()) {
+                            info!("[server socket] turned away banned {}", client_address.ip());
+                            spawn(turn_away(acceptor.clone(), stream, client_address));
+                        } else {
+                            spawn(accept_client(
+                                pause_token.clone(),
+                                new_client_ios.clone(),
+                                config.clone(),
+                                acceptor.clone(),
+                                stream,
+                                client_address,
+                            ));
                         }
-                        let stop_token = stop_token.clone();
-                        let pause_token = pause_token.clone();
-                        spawn(run_client_io(
-                            stop_token,
-                            pause_token,
-                            to_server,
-                            from_server,
-                            stream
-                        ));
                     }
                 },
                 _ = stop => {
-                    println!("[server socket] stop requested");
+                    info!("[server socket] stop requested");
                     break
                 },
             };
             pause_token.wait().await;
         }
-        println!("[server socket] stopped");
+        info!("[server socket] stopped");
     } else {
-        ui_event_sink
-            .submit_command(RUNTIME_ERROR, (), Target::Auto).ok();
+        error!("[server socket] failed to bind {}", config.address);
+        observer.notify(SessionEvent::Failed(format!("Failed to start the hive on {}.", config.address)));
+    }
+}
+
+/// Encrypts the connection if required.
+async fn encode(
+    acceptor: Option<TlsAcceptor>,
+    stream: TcpStream,
+    client_address: SocketAddr,
+) -> Option<EncodedSocket> {
+    let stream = match acceptor {
+        Some(acceptor) => {
+            let accepted = timeout(
+                Duration::from_secs_f32(HANDSHAKE_TIMEOUT),
+                transport::accept(&acceptor, stream),
+            )
+            .await;
+            match accepted {
+                Ok(Ok(stream)) => stream,
+                _ => {
+                    warn!("[server socket] encryption failed for {}", client_address);
+                    return None
+                }
+            }
+        }
+        None => transport::plain(stream),
+    };
+    Some(asynchronous_codec::Framed::new(stream, BincodeCodec::new()))
+}
+
+/// Tells a banned client it is not welcome.
+async fn turn_away(acceptor: Option<TlsAcceptor>, stream: TcpStream, client_address: SocketAddr) {
+    if let Some(mut stream) = encode(acceptor, stream, client_address).await {
+        stream.send(ServerMessage::Rejected(RejectReason::Banned)).await.ok();
+    }
+}
+
+/// Encrypts the connection if required and awaits the handshake of a new client.
+/// Registers the client and handles its communication if accepted.
+async fn accept_client(
+    pause_token: Arc<PauseToken>,
+    new_client_ios: Sender<NewClient>,
+    config: Arc<ServerConfig>,
+    acceptor: Option<TlsAcceptor>,
+    stream: TcpStream,
+    client_address: SocketAddr,
+) {
+    let mut stream = match encode(acceptor, stream, client_address).await {
+        Some(stream) => stream,
+        None => return,
+    };
+    let (handshake, room) = match handshake(&mut stream, &config.password).await {
+        Some(accepted) => accepted,
+        None => return,
+    };
+    let ip = client_address.ip();
+    let nickname = if handshake.nickname.is_empty() {
+        ip.to_string()
+    } else {
+        handshake.nickname
+    };
+    info!("[server socket] {} joined from {}", nickname, ip);
+    let (to_server, from_client) = bounded::<ClientMessage>(QUEUE_SIZE);
+    let (to_client, from_server) = outbox::outbox(OUTBOX_SIZE);
+    let (relayed, from_relays) = bounded::<ServerMessage>(QUEUE_SIZE);
+    let links = relay::links();
+    let client_io = ClientIO{
+        to: to_client,
+        relayed,
+        ip,
+        nickname,
+        room,
+        links: links.clone(),
+        connected_since: SystemTime::now(),
+        hosting: None,
+        latency: None,
+        host: false,
+    };
+    if new_client_ios.send((client_io, from_client)).await.is_err() {
+        error!("[server socket] state manager disconnected");
+        return
+    }
+    let channels = ClientChannels {
+        to_server,
+        from_server,
+        from_relays,
+        links,
+    };
+    run_client_io(pause_token, config, channels, stream).await;
+}
+
+/// Awaits the client handshake, the room and the password proof, if required.
+/// Replies with the verdict.
+/// Returns the handshake and the room if the client was accepted.
+async fn handshake(stream: &mut EncodedSocket, password: &Option<String>) -> Option<(Handshake, String)> {
+    let first_message = timeout(Duration::from_secs_f32(HANDSHAKE_TIMEOUT), stream.try_next()).await;
+    let verdict = match first_message {
+        Ok(Ok(Some(ClientMessage::Handshake(handshake)))) => validate_handshake(handshake),
+        Ok(Ok(None)) | Err(_) => return None,
+        Ok(_) => Err(RejectReason::HandshakeExpected),
+    };
+    let mut verdict = match verdict {
+        Ok(handshake) => join_room(stream).await?.map(|room| (handshake, room)),
+        Err(reason) => Err(reason),
+    };
+    if let (Ok(_), Some(password)) = (&verdict, password) {
+        if !authenticate(stream, password).await? {
+            verdict = Err(RejectReason::WrongPassword);
+        }
+    }
+    match verdict {
+        Ok(handshake) => {
+            stream.send(ServerMessage::Accepted).await.ok()?;
+            Some(handshake)
+        }
+        Err(reason) => {
+            warn!("[server socket] client rejected: {}", reason);
+            stream.send(ServerMessage::Rejected(reason)).await.ok();
+            None
+        }
+    }
+}
+
+/// Awaits the room the client wants to join.
+/// Returns `None` if the client did not send one.
+async fn join_room(stream: &mut EncodedSocket) -> Option<Result<String, RejectReason>> {
+    let message = timeout(Duration::from_secs_f32(HANDSHAKE_TIMEOUT), stream.try_next()).await;
+    match message {
+        Ok(Ok(Some(ClientMessage::JoinRoom(room)))) => {
+            if room.chars().count() > MAX_ROOM_LENGTH || room.contains('§') {
+                Some(Err(RejectReason::InvalidRoom))
+            } else {
+                Some(Ok(room))
+            }
+        }
+        Ok(Ok(Some(_))) => Some(Err(RejectReason::HandshakeExpected)),
+        _ => None,
+    }
+}
+
+/// Challenges the client to prove it knows the password.
+/// Returns `None` if the client did not respond.
+async fn authenticate(stream: &mut EncodedSocket, password: &str) -> Option<bool> {
+    let challenge = auth::new_challenge();
+    stream.send(ServerMessage::Challenge(challenge)).await.ok()?;
+    let response = timeout(Duration::from_secs_f32(HANDSHAKE_TIMEOUT), stream.try_next()).await;
+    match response {
+        Ok(Ok(Some(ClientMessage::ChallengeResponse(proof)))) => {
+            Some(auth::verify(password, &challenge, &proof))
+        }
+        Ok(Ok(Some(_))) => Some(false),
+        _ => None,
+    }
+}
+
+/// Checks if the client can join.
+fn validate_handshake(handshake: Handshake) -> Result<Handshake, RejectReason> {
+    if handshake.protocol_version != PROTOCOL_VERSION {
+        return Err(RejectReason::ProtocolMismatch(PROTOCOL_VERSION, APP_VERSION.to_string()));
+    }
+    if handshake.nickname.chars().count() > MAX_NICKNAME_LENGTH || handshake.nickname.contains('§') {
+        return Err(RejectReason::InvalidNickname);
+    }
+    Ok(handshake)
+}
+
+/// Writes a message to the client.
+/// Gives up if the client gets dropped for falling behind or the write takes longer than `limit`.
+/// Returns whether the message was written.
+async fn write(stream: &mut EncodedSocket, message: ServerMessage, inbox: &Inbox, limit: Duration) -> bool {
+    let send = timeout(limit, stream.send(message)).fuse();
+    let dropped = inbox.dropped().fuse();
+    pin_mut!(send);
+    pin_mut!(dropped);
+    select! {
+        sent = send => matches!(sent, Ok(Ok(()))),
+        _ = dropped => false,
     }
 }
 
 /// Sends and receives messages from a client.
+/// Answers and sends heartbeats.
 ///
 /// Stops if
 /// - to-server channel is terminated
-/// - from-server channel is terminated
-/// - socket is terminated
+/// - from-server channel is terminated, once the queued messages are sent
+/// - socket is terminated or stops taking data
+/// - client goes silent
+/// - client falls behind
 ///
+/// Follows the state manager when the server stops, so that clients hear about it.
 /// Pauses if pause token gets paused.
 async fn run_client_io(
-    stop_token: Arc<PauseToken>,
     pause_token: Arc<PauseToken>,
-    to_server: Sender<ClientMessage>,
-    from_server: Receiver<ServerMessage>,
+    config: Arc<ServerConfig>,
+    channels: ClientChannels,
     mut stream: EncodedSocket,
 ) {
-    println!("[client handler] started");
-    while stop_token.is_paused().await {
+    debug!("[client handler] started");
+    let ClientChannels { to_server, from_server, from_relays, links } = channels;
+    // A client that does not take a message for this long is as good as silent.
+    let limit = config.heartbeat.timeout;
+    let mut heartbeat = Heartbeat::new(config.heartbeat);
+    loop {
         let send = from_server.recv().fuse();
+        let relayed = from_relays.recv().fuse();
         let recv = stream.try_next().fuse();
-        let stop = stop_token.wait().fuse();
+        let beat = sleep(heartbeat.until_next()).fuse();
         pin_mut!(send);
+        pin_mut!(relayed);
         pin_mut!(recv);
-        pin_mut!(stop);
+        pin_mut!(beat);
 
         select! {
             send = send => {
                 match send {
-                    Ok(message) => {
-                        if let Err(_) = stream.send(message).await {
-                            println!("[client handler] client disconnected");
+                    Some(message) => {
+                        if !write(&mut stream, message, &from_server, limit).await {
+                            debug!("[client handler] client disconnected");
                             break
                         }
                     }
-                    Err(_) => {
-                        println!("[client handler] state manager disconnected");
+                    None if from_server.is_dropped() => {
+                        warn!("[client handler] client fell behind, dropped");
+                        break
+                    },
+                    // Kicked or the server stopped.
+                    None => {
+                        debug!("[client handler] released by the state manager");
                         break
                     },
                 }
             },
+            relayed = relayed => {
+                // Relays close with the server, the state manager releases the client then.
+                if let Ok(message) = relayed {
+                    if !write(&mut stream, message, &from_server, limit).await {
+                        debug!("[client handler] client disconnected");
+                        break
+                    }
+                }
+            },
             recv = recv => {
                 match recv {
-                    Ok(opt_message) => {
-                        if let Some(message) = opt_message {
-                            if let Err(_) = to_server.send(message).await {
-                                println!("[client handler] state manager disconnected");
-                                break
-                            }
+                    Ok(Some(message)) => {
+                        heartbeat.seen();
+                        match message {
+                            ClientMessage::Ping(nonce) => {
+                                if !write(&mut stream, ServerMessage::Pong(nonce), &from_server, limit).await {
+                                    debug!("[client handler] client disconnected");
+                                    break
+                                }
+                            },
+                            ClientMessage::Pong(nonce) => {
+                                heartbeat.pong(nonce);
+                            },
+                            // Answered right away, queueing would skew the estimate.
+                            ClientMessage::TimeRequest(sent) => {
+                                if !write(&mut stream, ServerMessage::TimeReply(sent, timer::now()), &from_server, limit).await {
+                                    debug!("[client handler] client disconnected");
+                                    break
+                                }
+                            },
+                            // Game traffic never goes through the state manager.
+                            ClientMessage::RelayData(id, data) => {
+                                relay::forward(&links, id, data).await;
+                            },
+                            ClientMessage::RelayClose(id) => {
+                                relay::unlink(&links, id).await;
+                            },
+                            // The state manager trusts the keys it gets, wrong ones stop here.
+                            ClientMessage::HostKey(key) if Some(key) != config.host_key => {
+                                warn!("[client handler] wrong host key");
+                            },
+                            message => {
+                                if to_server.send(message).await.is_err() {
+                                    error!("[client handler] state manager disconnected");
+                                    break
+                                }
+                            },
                         }
                     },
-                    Err(_) => {
-                        println!("[client handler] client disconnected");
+                    Ok(None) | Err(_) => {
+                        debug!("[client handler] client disconnected");
                         break
                     },
                 }
             },
-            _ = stop => {
-                println!("[client handler] stop requested");
-                break
+            _ = beat => {
+                match heartbeat.poll() {
+                    Some(HeartbeatAction::Ping(nonce)) => {
+                        if !write(&mut stream, ServerMessage::Ping(nonce), &from_server, limit).await {
+                            debug!("[client handler] client disconnected");
+                            break
+                        }
+                    },
+                    Some(HeartbeatAction::TimedOut) => {
+                        warn!("[client handler] client timed out");
+                        break
+                    },
+                    None => {},
+                }
             },
         };
 
         pause_token.wait().await;
     }
-    println!("[client handler] stopped");
+    debug!("[client handler] stopped");
 }
 
 /// Creates incremental u64 ids starting from 1.
@@ -204,74 +575,217 @@ impl IdDistributor {
 
 /// Internal server state.
 struct ServerState {
-    lan_games: HashMap<u64, SocketAddr>,
+    lan_games: HashMap<u64, LanGame>,
+    timer: TimerState,
+    /// IP relay ports are opened on, games are not relayed if not set.
+    relay: Option<IpAddr>,
+    /// Open relays, by host.
+    relays: HashMap<u64, Relay>,
+    policy: HostPolicy,
+}
+
+/// Public port of a relayed game.
+struct Relay {
+    /// Address the game would have without the relay.
+    game: SocketAddr,
+    stop_token: Arc<PauseToken>,
+}
+
+impl ServerState {
+    fn new(relay: Option<IpAddr>, policy: HostPolicy) -> Self {
+        Self {
+            lan_games: HashMap::new(),
+            timer: TimerState::Reset,
+            relay,
+            relays: HashMap::new(),
+            policy,
+        }
+    }
+}
+
+/// Clients sharing games, the timer and the chat.
+/// Clients of different rooms never hear of each other.
+struct Room {
+    state: ServerState,
+    client_ios: ClientIOs,
+    /// Since when nobody is in, the room expires after a while.
+    empty_since: Option<SystemTime>,
+}
+
+/// All rooms of the server.
+struct Hive {
+    rooms: HashMap<String, Room>,
+    /// Room of every client.
+    room_of: HashMap<u64, String>,
+    relay: Option<IpAddr>,
+    /// Applies to every room.
+    policy: HostPolicy,
+}
+
+impl Hive {
+    fn new(relay: Option<IpAddr>) -> Self {
+        Self {
+            rooms: HashMap::new(),
+            room_of: HashMap::new(),
+            relay,
+            policy: HostPolicy::ShowAll,
+        }
+    }
+
+    /// Puts the client in its room, opens the room if needed.
+    fn enter(&mut self, id: u64, mut client_io: ClientIO, at: SystemTime) {
+        let name = client_io.room.clone();
+        let (relay, policy) = (self.relay, &self.policy);
+        let room = self.rooms.entry(name.clone()).or_insert_with(|| {
+            info!("[state manager] room {:?} opened", name);
+            Room {
+                state: ServerState::new(relay, policy.clone()),
+                client_ios: HashMap::new(),
+                empty_since: None,
+            }
+        });
+        room.empty_since = None;
+        client_io.connected_since = at;
+        room.client_ios.insert(id, client_io);
+        self.room_of.insert(id, name);
+    }
+
+    /// Room of a connected client.
+    fn room(&mut self, id: u64) -> Option<&mut Room> {
+        let name = self.room_of.get(&id)?;
+        self.rooms.get_mut(name)
+    }
+
+    /// Some empty room kept for longer than `expiry`.
+    fn expired(&self, now: SystemTime, expiry: Duration) -> Option<String> {
+        self.rooms
+            .iter()
+            .find(|(_, room)| {
+                room.empty_since
+                    .map_or(false, |since| now.duration_since(since).unwrap_or_default() >= expiry)
+            })
+            .map(|(name, _)| name.clone())
+    }
+
+    /// Time until the next empty room expires.
+    fn next_expiry(&self, now: SystemTime, expiry: Duration) -> Option<Duration> {
+        self.rooms
+            .values()
+            .filter_map(|room| room.empty_since)
+            .map(|since| (since + expiry).duration_since(now).unwrap_or_default())
+            .min()
+    }
 }
 
 /// Possible update causes for the server.
 enum ServerWakeupCause {
     NewMessage(Result<ClientMessage, ()>, u64),
-    NewClient(ClientIO),
-    Stop,
+    NewClient(NewClient),
+    Command(ServerCommand),
+    /// Some empty room is due to expire.
+    Expire,
+    /// Server stops, or lost the host's channels.
+    Stop(ShutdownReason),
 }
 
 /// No client connected.
-/// Await stop or new client.
+/// Await stop, new client or host command.
 async fn await_wakeup_no_clients(
     stop_token: &Arc<PauseToken>,
-    new_client_ios: &Receiver<ClientIO>,
+    new_client_ios: &Receiver<NewClient>,
+    commands: &Receiver<ServerCommand>,
+    expiry: Option<Duration>,
 ) -> ServerWakeupCause {
     let new = new_client_ios.recv().fuse();
+    let command = commands.recv().fuse();
+    let expire = expiration(expiry).fuse();
     let stop = stop_token.wait().fuse();
     pin_mut!(new);
+    pin_mut!(command);
+    pin_mut!(expire);
     pin_mut!(stop);
     select! {
         result = new => {
             if let Ok(client_io) = result {
                 return ServerWakeupCause::NewClient(client_io)
             } else {
-                return ServerWakeupCause::Stop
+                return ServerWakeupCause::Stop(ShutdownReason::Abandoned)
             }
         },
+        result = command => {
+            if let Ok(command) = result {
+                return ServerWakeupCause::Command(command)
+            } else {
+                return ServerWakeupCause::Stop(ShutdownReason::Abandoned)
+            }
+        },
+        _ = expire => {
+            return ServerWakeupCause::Expire
+        },
         _ = stop => {
-            return ServerWakeupCause::Stop
+            return ServerWakeupCause::Stop(ShutdownReason::Stopped)
         },
     };
 }
 
+/// Completes after the wait, never if there is nothing to wait for.
+async fn expiration(wait: Option<Duration>) {
+    match wait {
+        Some(wait) => sleep(wait).await,
+        None => future::pending().await,
+    }
+}
+
+/// Tags the messages of a client with its id.
+fn client_events(id: u64, from: Receiver<ClientMessage>) -> stream::BoxStream<'static, (u64, Option<ClientMessage>)> {
+    from.map(move |message| (id, Some(message)))
+        .chain(stream::once(async move { (id, None) }))
+        .boxed()
+}
+
+/// Some client connected.
+/// Await stop, client message, new client or host command.
 async fn await_wakeup(
     stop_token: &Arc<PauseToken>,
-    new_client_ios: &Receiver<ClientIO>,
-    client_ios: &mut ClientIOs,
+    new_client_ios: &Receiver<NewClient>,
+    commands: &Receiver<ServerCommand>,
+    events: &mut ClientEvents,
+    expiry: Option<Duration>,
 ) -> ServerWakeupCause {
-    let iterator = client_ios.iter().map(|(id, client_io)| {
-        let result = async move {
-            let result = client_io.from.recv().await;
-            if let Ok(message) = result {
-                (Ok(message), *id)
-            } else {
-                (Err(()), *id)
-            }
-        };
-        Box::pin(result)
-    });
-    let mut recv = futures::future::select_all(iterator).fuse();
+    // Stays pending once the last client is gone.
+    let recv = events.select_next_some();
     let new = new_client_ios.recv().fuse();
+    let command = commands.recv().fuse();
+    let expire = expiration(expiry).fuse();
     let stop = stop_token.wait().fuse();
+    pin_mut!(recv);
     pin_mut!(new);
+    pin_mut!(command);
+    pin_mut!(expire);
     pin_mut!(stop);
     select! {
-        ((result, id), _, _) = recv => {
-            return ServerWakeupCause::NewMessage(result, id)
+        (id, message) = recv => {
+            return ServerWakeupCause::NewMessage(message.ok_or(()), id)
         },
         result = new => {
             if let Ok(client_io) = result {
                 return ServerWakeupCause::NewClient(client_io)
             } else {
-                return ServerWakeupCause::Stop
+                return ServerWakeupCause::Stop(ShutdownReason::Abandoned)
+            }
+        },
+        result = command => {
+            if let Ok(command) = result {
+                return ServerWakeupCause::Command(command)
+            } else {
+                return ServerWakeupCause::Stop(ShutdownReason::Abandoned)
             }
         },
+        _ = expire => {
+            return ServerWakeupCause::Expire
+        },
         _ = stop => {
-            return ServerWakeupCause::Stop
+            return ServerWakeupCause::Stop(ShutdownReason::Stopped)
         },
     };
 }
@@ -279,173 +793,578 @@ async fn await_wakeup(
 /// Receives updates from clients.
 /// Updates internal state.
 /// Spreads new state to clients.
+/// Records every state input, if configured.
 async fn server_state_manager(
-    ui_event_sink: ExtEventSink,
+    observer: Observer,
     stop_token: Arc<PauseToken>,
     pause_token: Arc<PauseToken>,
-    new_client_ios: Receiver<ClientIO>,
+    new_client_ios: Receiver<NewClient>,
+    commands: Receiver<ServerCommand>,
+    bans: Bans,
+    config: Arc<ServerConfig>,
 ) {
-    println!("[state manager] started");
-    let mut state = ServerState {
-        lan_games: HashMap::new(),
+    info!("[state manager] started");
+    let relay = match config.relay {
+        true => Some(config.address.ip()),
+        false => None,
     };
-    let mut client_ios: ClientIOs = HashMap::new();
+    let room_expiry = config.room_expiry;
+    let mut hive = Hive::new(relay);
+    let mut events: ClientEvents = stream::SelectAll::new();
     let mut id_distributor = IdDistributor::new();
+    let mut recorder = config.recording.as_ref().and_then(|path| match Recorder::open(path) {
+        Ok(recorder) => Some(recorder),
+        Err(error) => {
+            error!("[state manager] failed to open recording {}: {}", path.display(), error);
+            None
+        }
+    });
+    record(&mut recorder, SystemTime::now(), &StateEvent::Started(PROTOCOL_VERSION));
+    let at = SystemTime::now();
+    let event = StateEvent::Policy(config.policy.clone());
+    record(&mut recorder, at, &event);
+    apply(&observer, &mut hive, &bans, event, at).await;
 
+    let mut reason = ShutdownReason::Stopped;
     while stop_token.is_paused().await {
+        let expiry = hive.next_expiry(SystemTime::now(), room_expiry);
         let update: ServerWakeupCause;
-        if client_ios.len() == 0 {
-            update = await_wakeup_no_clients(&stop_token, &new_client_ios).await;
+        if events.is_empty() {
+            update = await_wakeup_no_clients(&stop_token, &new_client_ios, &commands, expiry).await;
         } else {
-            update = await_wakeup(&stop_token, &new_client_ios, &mut client_ios).await;
+            update = await_wakeup(&stop_token, &new_client_ios, &commands, &mut events, expiry).await;
         }
 
-        match update {
-            ServerWakeupCause::Stop => {
-                println!("[state manager] stop requested");
+        let at = SystemTime::now();
+        let event = match update {
+            ServerWakeupCause::Stop(cause) => {
+                info!("[state manager] stop requested");
+                reason = cause;
                 break
             },
-            ServerWakeupCause::NewClient(client_io) => {
-                client_ios.insert(id_distributor.next(), client_io);
-                ui_event_sink
-                    .submit_command(USER_COUNT, client_ios.len(), Target::Auto)
-                    .unwrap();
+            ServerWakeupCause::NewClient((client_io, from)) => {
+                let id = id_distributor.next();
+                let event = StateEvent::Joined(id, client_io.nickname.clone(), client_io.ip, client_io.room.clone());
+                hive.enter(id, client_io, at);
+                events.push(client_events(id, from));
+                event
+            }
+            // Kicked clients are heard from until their handler stops.
+            ServerWakeupCause::NewMessage(_, id) if !hive.room_of.contains_key(&id) => continue,
+            // Client handler checked the key, recordings keep only that it was right.
+            ServerWakeupCause::NewMessage(Ok(ClientMessage::HostKey(_)), id) => {
+                StateEvent::Message(id, ClientMessage::HostKey(0))
+            },
+            ServerWakeupCause::NewMessage(Ok(message), id) => StateEvent::Message(id, message),
+            ServerWakeupCause::NewMessage(Err(_), id) => StateEvent::Left(id),
+            ServerWakeupCause::Command(command) => StateEvent::Command(command),
+            ServerWakeupCause::Expire => match hive.expired(at, room_expiry) {
+                Some(name) => StateEvent::Expired(name),
+                None => continue,
+            },
+        };
+        record(&mut recorder, at, &event);
+        apply(&observer, &mut hive, &bans, event, at).await;
+
+        pause_token.wait().await;
+    }
+    for room in hive.rooms.values_mut() {
+        // Client handlers send it and stop once their outbox is dropped.
+        send_to_all(&observer, &mut room.client_ios, ServerMessage::ServerShutdown(reason)).await;
+        for (_, relay) in room.state.relays.drain() {
+            relay.stop_token.resume().await;
+        }
+    }
+    info!("[state manager] stopped");
+}
+
+/// Appends the event to the recording.
+/// Stops recording if that fails.
+fn record(recorder: &mut Option<Recorder>, at: SystemTime, event: &StateEvent) {
+    if let Some(active) = recorder {
+        if let Err(error) = active.record(at, event.clone()) {
+            error!("[state manager] failed to record, recording stopped: {}", error);
+            *recorder = None;
+        }
+    }
+}
+
+/// Updates server state based on the event.
+/// Joining clients have to be in their room already.
+async fn apply(
+    observer: &Observer,
+    hive: &mut Hive,
+    bans: &Bans,
+    event: StateEvent,
+    at: SystemTime,
+) {
+    match event {
+        StateEvent::Started(_) => {}
+        StateEvent::Policy(policy) => {
+            for room in hive.rooms.values_mut() {
+                let shown = shown_games(&room.state);
+                room.state.policy = policy.clone();
+                games_changed(observer, &room.state, &mut room.client_ios, shown).await;
+            }
+            hive.policy = policy;
+        }
+        StateEvent::Joined(id, _, _, _) => {
+            if let Some(room) = hive.room(id) {
+                joined(observer, &room.state, &mut room.client_ios, id).await;
+            }
+            members_changed(observer, hive);
+        }
+        StateEvent::Left(id) => {
+            left(observer, hive, id, at).await;
+        }
+        StateEvent::Message(id, message) => {
+            if let Some(room) = hive.room(id) {
+                let before = room.client_ios.get(&id).map(|client_io| client_io.member(id));
+                update_state(observer, &mut room.state, &mut room.client_ios, id, message, at).await;
+                let after = room.client_ios.get(&id).map(|client_io| client_io.member(id));
+                if before != after {
+                    member_changed(observer, &mut room.client_ios, id).await;
+                }
             }
-            ServerWakeupCause::NewMessage(result, id) => {
-                if let Ok(message) = result {
-                    update_state(&ui_event_sink, &mut state, &mut client_ios, id, message).await;
-                } else {
-                    client_ios.remove(&id);
-                    update_state(
-                        &ui_event_sink,
-                        &mut state,
-                        &mut client_ios,
-                        id,
-                        ClientMessage::StoppedHosting,
-                    )
-                    .await;
-                    ui_event_sink
-                        .submit_command(USER_COUNT, client_ios.len(), Target::Auto)
-                        .unwrap();
+        }
+        StateEvent::Command(ServerCommand::Kick(id)) => {
+            kick(observer, hive, id, false, at).await;
+        }
+        StateEvent::Command(ServerCommand::Ban(id)) => {
+            let ip = hive.room(id).and_then(|room| room.client_ios.get(&id)).map(|client_io| client_io.ip);
+            // Banning the host's IP would lock the host out as well.
+            let host_ip = |ip: IpAddr| {
+                hive.rooms
+                    .values()
+                    .flat_map(|room| room.client_ios.values())
+                    .any(|client_io| client_io.host && client_io.ip == ip)
+            };
+            if let Some(ip) = ip.filter(|ip| host_ip(*ip)) {
+                warn!("[state manager] refused to ban {}, the host connects from it", ip);
+            } else if let Some(ip) = ip {
+                info!("[state manager] banned {}", ip);
+                bans.lock().await.insert(ip);
+                // Every room, bans hold for the whole server.
+                let ids: Vec<u64> = hive
+                    .rooms
+                    .values()
+                    .flat_map(|room| room.client_ios.iter())
+                    .filter(|(_, client_io)| client_io.ip == ip)
+                    .map(|(id, _)| *id)
+                    .collect();
+                for id in ids {
+                    kick(observer, hive, id, true, at).await;
                 }
             }
         }
+        StateEvent::Expired(name) => {
+            if hive.rooms.get(&name).map_or(false, |room| room.client_ios.is_empty()) {
+                info!("[state manager] room {:?} expired", name);
+                hive.rooms.remove(&name);
+            }
+        }
+    }
+}
 
-        pause_token.wait().await;
+/// Feeds a recording through the state logic.
+/// Every `Started` record begins with a fresh state.
+/// Relays are not opened again, relayed games show up at their direct address.
+pub async fn replay(records: Vec<Record>) -> Vec<Replayed> {
+    let (observer, _events) = ChannelObserver::new();
+    let observer: Observer = Arc::new(observer);
+    let bans: Bans = Arc::new(Mutex::new(HashSet::new()));
+    let mut hive = Hive::new(None);
+    // Receiving ends of the messages sent to each client.
+    let mut inboxes: HashMap<u64, Inbox> = HashMap::new();
+    let mut replayed = Vec::new();
+    for record in records {
+        match &record.event {
+            StateEvent::Started(_) => {
+                hive = Hive::new(None);
+                inboxes.clear();
+            }
+            StateEvent::Joined(id, nickname, ip, room) => {
+                let (to, inbox) = outbox::outbox(OUTBOX_SIZE);
+                let (relayed, _) = bounded(1);
+                let client_io = ClientIO{
+                    to,
+                    relayed,
+                    ip: *ip,
+                    nickname: nickname.clone(),
+                    room: room.clone(),
+                    links: relay::links(),
+                    connected_since: record.at,
+                    hosting: None,
+                    latency: None,
+                    host: false,
+                };
+                hive.enter(*id, client_io, record.at);
+                inboxes.insert(*id, inbox);
+            }
+            _ => {}
+        }
+        apply(&observer, &mut hive, &bans, record.event.clone(), record.at).await;
+        let mut sent = Vec::new();
+        let mut ids: Vec<u64> = inboxes.keys().copied().collect();
+        ids.sort();
+        for id in ids {
+            while let Some(message) = inboxes[&id].try_recv() {
+                sent.push((id, message));
+            }
+        }
+        inboxes.retain(|id, _| hive.room_of.contains_key(id));
+        replayed.push(Replayed { record, sent });
+    }
+    replayed
+}
+
+/// Client left or got removed.
+/// Closes its game and tells the room, the room starts expiring once empty.
+async fn left(
+    observer: &Observer,
+    hive: &mut Hive,
+    id: u64,
+    at: SystemTime,
+) {
+    if let Some(room) = hive.room(id) {
+        room.client_ios.remove(&id);
+        update_state(observer, &mut room.state, &mut room.client_ios, id, ClientMessage::StoppedHosting, at).await;
+        send_to_all(observer, &mut room.client_ios, ServerMessage::MemberLeft(id)).await;
+        if room.client_ios.is_empty() {
+            room.empty_since = Some(at);
+        }
+    }
+    hive.room_of.remove(&id);
+    members_changed(observer, hive);
+}
+
+/// Tells the client it was kicked and drops it.
+/// Client handler closes the socket once the message is sent.
+async fn kick(
+    observer: &Observer,
+    hive: &mut Hive,
+    id: u64,
+    banned: bool,
+    at: SystemTime,
+) {
+    let room = match hive.room(id) {
+        Some(room) => room,
+        None => return,
+    };
+    if let Some(client_io) = room.client_ios.get(&id) {
+        if client_io.host {
+            warn!("[state manager] refused to kick the host");
+            return
+        }
+        info!("[state manager] kicked {}", client_io.nickname);
+        send_to_one(observer, &mut room.client_ios, ServerMessage::Kicked(banned), id).await;
+        left(observer, hive, id, at).await;
+    }
+}
+
+/// Reports connected clients of every room to the host.
+/// Only on joining and leaving, the host hears of other changes one client at a time.
+fn members_changed(observer: &Observer, hive: &Hive) {
+    let mut members: Vec<Member> = hive
+        .rooms
+        .values()
+        .flat_map(|room| room.client_ios.iter())
+        .map(|(id, client_io)| client_io.member(*id))
+        .collect();
+    members.sort_by_key(|member| member.id);
+    observer.notify(SessionEvent::Members(members));
+}
+
+/// Tells the host and the room about the client, the client included.
+async fn member_changed(observer: &Observer, client_ios: &mut ClientIOs, id: u64) {
+    if let Some(member) = client_ios.get(&id).map(|client_io| client_io.member(id)) {
+        observer.notify(SessionEvent::MemberChanged(member.clone()));
+        send_to_all(observer, client_ios, ServerMessage::MemberChanged(member)).await;
     }
-    println!("[state manager] stopped");
+}
+
+/// Members of the room, in joining order.
+fn roster(client_ios: &ClientIOs) -> Vec<Member> {
+    let mut members: Vec<Member> = client_ios
+        .iter()
+        .map(|(id, client_io)| client_io.member(*id))
+        .collect();
+    members.sort_by_key(|member| member.id);
+    members
 }
 
 /// Updates server state based on the message.
 /// Sends the update to client/-s.
 async fn update_state(
-    ui_event_sink: &ExtEventSink,
+    observer: &Observer,
     state: &mut ServerState,
     client_ios: &mut ClientIOs,
     id: u64,
     message: ClientMessage,
+    at: SystemTime,
 ) {
     match message {
         ClientMessage::StoppedHosting => {
-            stopped_hosting(ui_event_sink, state, client_ios, id).await;
+            stopped_hosting(observer, state, client_ios, id).await;
         }
         ClientMessage::StartedHosting(port) => {
-            started_hosting(ui_event_sink, state, client_ios, id, port).await;
+            started_hosting(observer, state, client_ios, id, port, at).await;
+        }
+        ClientMessage::TimerStart(at) => {
+            let timer = state.timer.start(at);
+            timer_changed(observer, state, client_ios, timer).await;
+        }
+        ClientMessage::TimerStop(at) => {
+            let timer = state.timer.stop(at);
+            timer_changed(observer, state, client_ios, timer).await;
         }
-        ClientMessage::Joined => {
-            joined(ui_event_sink, state, client_ios, id).await;
+        ClientMessage::TimerReset => {
+            timer_changed(observer, state, client_ios, TimerState::Reset).await;
         }
+        ClientMessage::Chat(content) => {
+            chat(observer, client_ios, id, content, at).await;
+        }
+        ClientMessage::Latency(latency) => {
+            if let Some(client_io) = client_ios.get_mut(&id) {
+                client_io.latency = Some(latency);
+            }
+        }
+        // Client handler lets only the right key through.
+        ClientMessage::HostKey(_) => {
+            if let Some(client_io) = client_ios.get_mut(&id) {
+                client_io.host = true;
+            }
+        }
+        // Only valid before joining.
+        ClientMessage::Handshake(_) | ClientMessage::ChallengeResponse(_) | ClientMessage::JoinRoom(_) => {}
+        // Handled by the client handler.
+        ClientMessage::Ping(_)
+        | ClientMessage::Pong(_)
+        | ClientMessage::TimeRequest(_)
+        | ClientMessage::RelayData(_, _)
+        | ClientMessage::RelayClose(_) => {}
     }
 }
 
 /// Client stopped a LAN game.
 async fn stopped_hosting(
-    ui_event_sink: &ExtEventSink,
+    observer: &Observer,
     state: &mut ServerState,
     client_ios: &mut ClientIOs,
     id: u64,
 ) {
-    let prev_len = state.lan_games.len();
-    state.lan_games.remove(&id);
-    let post_len = state.lan_games.len();
-    if prev_len.clamp(0, 2) != post_len.clamp(0, 2) {
-        let message = state_into_message(state);
-        send_to_all(ui_event_sink, client_ios, message).await;
+    if let Some(relay) = state.relays.remove(&id) {
+        relay.stop_token.resume().await;
+    }
+    if let Some(client_io) = client_ios.get_mut(&id) {
+        client_io.hosting = None;
+    }
+    let shown = shown_games(state);
+    if state.lan_games.remove(&id).is_some() {
+        games_changed(observer, state, client_ios, shown).await;
     }
 }
 
 /// Client started a LAN game.
 async fn started_hosting(
-    ui_event_sink: &ExtEventSink,
+    observer: &Observer,
     state: &mut ServerState,
     client_ios: &mut ClientIOs,
     id: u64,
     port: u16,
+    at: SystemTime,
 ) {
-    let prev_len = state.lan_games.len();
-    let ip;
-    {
-        let client_io = client_ios.get(&id).unwrap();
-        ip = client_io.ip.clone();
-    }
-    let address = SocketAddr::new(ip, port);
-    let result = state.lan_games.insert(id, address.clone());
-    let post_len = state.lan_games.len();
-    if (prev_len.clamp(0, 2) != post_len.clamp(0, 2))
-        || (if let Some(prev_address) = result {
-            prev_address != address
-        } else {
-            false
-        })
-    {
+    let client_io = match client_ios.get(&id) {
+        Some(client_io) => client_io,
+        None => return,
+    };
+    let direct = SocketAddr::new(client_io.ip, port);
+    if let Some(game) = state.lan_games.get(&id) {
+        let hosted = state.relays.get(&id).map_or(game.address, |relay| relay.game);
+        if hosted == direct {
+            return
+        }
+    }
+    let address = match state.relay {
+        Some(ip) => open_relay(state, client_io, id, ip, direct).await.unwrap_or(direct),
+        None => direct,
+    };
+    let game = LanGame {
+        id,
+        nickname: client_io.nickname.clone(),
+        address,
+        opened_at: at,
+    };
+    let shown = shown_games(state);
+    state.lan_games.insert(id, game);
+    if let Some(client_io) = client_ios.get_mut(&id) {
+        client_io.hosting = Some(port);
+    }
+    games_changed(observer, state, client_ios, shown).await;
+}
+
+/// Tells everyone about the shown games, unless they stayed the same.
+/// Games waiting behind the shown one change nothing for the clients.
+async fn games_changed(
+    observer: &Observer,
+    state: &ServerState,
+    client_ios: &mut ClientIOs,
+    shown: Vec<LanGame>,
+) {
+    if shown_games(state) != shown {
         let message = state_into_message(state);
-        send_to_all(ui_event_sink, client_ios, message).await;
+        send_to_all(observer, client_ios, message).await;
+    }
+}
+
+/// Opens a relay for the game of the client, closing the previous one.
+/// Returns the address to advertise, with an unspecified IP so that clients use the hive's.
+async fn open_relay(
+    state: &mut ServerState,
+    client_io: &ClientIO,
+    id: u64,
+    ip: IpAddr,
+    game: SocketAddr,
+) -> Option<SocketAddr> {
+    if let Some(relay) = state.relays.remove(&id) {
+        relay.stop_token.resume().await;
     }
+    let stop_token = Arc::new(PauseToken::new(true));
+    let opened = relay::open(
+        stop_token.clone(),
+        SocketAddr::new(ip, 0),
+        client_io.relayed.clone(),
+        client_io.links.clone(),
+    )
+    .await;
+    match opened {
+        Ok(address) => {
+            info!("[state manager] relaying {}'s game on port {}", client_io.nickname, address.port());
+            state.relays.insert(id, Relay { game, stop_token });
+            Some(SocketAddr::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), address.port()))
+        }
+        Err(error) => {
+            warn!("[state manager] failed to open a relay, advertising the direct address: {}", error);
+            None
+        }
+    }
+}
+
+/// Someone pressed a timer button.
+async fn timer_changed(
+    observer: &Observer,
+    state: &mut ServerState,
+    client_ios: &mut ClientIOs,
+    timer: TimerState,
+) {
+    if state.timer != timer {
+        info!("[state manager] timer {:?}", timer);
+        state.timer = timer;
+        send_to_all(observer, client_ios, ServerMessage::Timer(timer)).await;
+    }
+}
+
+/// Spreads a chat message to everyone, the sender included.
+/// Empty and overlong texts are dropped.
+async fn chat(
+    observer: &Observer,
+    client_ios: &mut ClientIOs,
+    id: u64,
+    content: ChatContent,
+    at: SystemTime,
+) {
+    let content = match content {
+        ChatContent::Text(text) => {
+            let text = text.trim();
+            if text.is_empty() || text.chars().count() > MAX_CHAT_LENGTH {
+                return
+            }
+            ChatContent::Text(text.to_string())
+        }
+        ping => ping,
+    };
+    let nickname = match client_ios.get(&id) {
+        Some(client_io) => client_io.nickname.clone(),
+        None => return,
+    };
+    let line = ChatLine { nickname, sent_at: at, content };
+    send_to_all(observer, client_ios, ServerMessage::Chat(line)).await;
 }
 
 /// Client joined.
-/// Send him the current status.
+/// Send him the current status and the roster, tell the others about him.
 async fn joined(
-    ui_event_sink: &ExtEventSink,
+    observer: &Observer,
     state: &ServerState,
     client_ios: &mut ClientIOs,
     id: u64,
 ) {
     let message = state_into_message(state);
-    send_to_one(ui_event_sink, client_ios, message, id).await;
+    send_to_one(observer, client_ios, message, id).await;
+    // Clients start with a reset timer.
+    if state.timer != TimerState::Reset {
+        send_to_one(observer, client_ios, ServerMessage::Timer(state.timer), id).await;
+    }
+    send_to_one(observer, client_ios, ServerMessage::Roster(roster(client_ios)), id).await;
+    if let Some(member) = client_ios.get(&id).map(|client_io| client_io.member(id)) {
+        for (other, client_io) in client_ios.iter_mut() {
+            if *other != id {
+                let _ = client_io.to.send(ServerMessage::MemberChanged(member.clone()));
+            }
+        }
+    }
 }
 
+/// Open games the policy lets through, oldest first.
+fn shown_games(state: &ServerState) -> Vec<LanGame> {
+    let mut games: Vec<LanGame> = state.lan_games.values().cloned().collect();
+    games.sort_by_key(|game| (game.opened_at, game.id));
+    let shown = match &state.policy {
+        HostPolicy::ShowAll => return games,
+        HostPolicy::FirstOpened => games.first(),
+        HostPolicy::MostRecent => games.last(),
+        HostPolicy::Priority(nicknames) => games.iter().min_by_key(|game| {
+            nicknames
+                .iter()
+                .position(|nickname| *nickname == game.nickname)
+                .unwrap_or(nicknames.len())
+        }),
+    };
+    shown.cloned().into_iter().collect()
+}
+
+/// Lists the shown games.
 fn state_into_message(state: &ServerState) -> ServerMessage {
-    match state.lan_games.len() {
+    let mut games = shown_games(state);
+    match games.len() {
         0 => ServerMessage::NoHost,
-        1 => {
-            let (_, address) = state.lan_games.iter().next().unwrap();
-            ServerMessage::OneHost(address.clone())
-        }
-        _ => ServerMessage::ManyHosts,
+        1 => ServerMessage::OneHost(games.pop().unwrap()),
+        _ => ServerMessage::ManyHosts(games),
     }
 }
 
 /// Send message to the targeted client.
+/// Never waits, a client that fell behind is dropped instead.
 async fn send_to_one(
-    _ui_event_sink: &ExtEventSink,
+    _observer: &Observer,
     client_ios: &mut ClientIOs,
     message: ServerMessage,
     target_id: u64,
 ) {
     let result = client_ios.get_mut(&target_id);
     if let Some(client_io) = result {
-        let _ = client_io.to.send(message).await;
+        let _ = client_io.to.send(message);
     }
 }
 
 /// Send message to all clients.
+/// Never waits, a client that fell behind is dropped instead.
 async fn send_to_all(
-    _ui_event_sink: &ExtEventSink,
+    _observer: &Observer,
     client_ios: &mut ClientIOs,
     message: ServerMessage,
 ) {
     for (_id, client_io) in client_ios.iter_mut() {
-        let _ = client_io.to.send(message.clone()).await;
+        let _ = client_io.to.send(message.clone());
     }
 }
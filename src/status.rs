@@ -0,0 +1,345 @@
+//! Local status API for stream overlays and bots.
+//!
+//! - `StatusObserver` keeps a snapshot of the session, fed by its events.
+//! - `serve` answers `GET /status` with the snapshot as JSON
+//!   and `GET /events` with a server-sent event stream of changes.
+//! - There is no authentication, keep it on localhost unless the network is trusted.
+
+use std::net::{Ipv4Addr, SocketAddr};
+use std::sync::{Arc, Mutex, atomic::{AtomicBool, Ordering}};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use async_std::channel::{bounded, Receiver, Sender};
+use async_std::future::timeout;
+use async_std::io::{ReadExt, WriteExt};
+use async_std::net::{Shutdown, TcpListener, TcpStream};
+use async_std::task::spawn;
+use futures::{FutureExt, pin_mut, select};
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::client::ConnectionStatus;
+use crate::messages::ChatLine;
+use crate::observer::{SessionEvent, SessionObserver};
+use crate::sync::PauseToken;
+use crate::timer::TimerView;
+
+/// Port the API listens on by default.
+pub const STATUS_PORT: u16 = 2138;
+
+/// Longest request head, in bytes.
+const MAX_REQUEST_SIZE: usize = 8 * 1024;
+
+/// Time for a request to arrive, in seconds.
+const REQUEST_TIMEOUT: f32 = 5.;
+
+/// Time between comments on an idle event stream, in seconds.
+/// Lets the stream notice closed connections and keeps proxies from dropping it.
+const KEEP_ALIVE_INTERVAL: f32 = 15.;
+
+/// Events queued for an event stream.
+/// A subscriber that lets the queue fill up stopped reading and is dropped.
+pub const SUBSCRIBER_QUEUE_SIZE: usize = 256;
+
+/// Default address, reachable from this machine only.
+pub fn default_address() -> SocketAddr {
+    SocketAddr::from((Ipv4Addr::LOCALHOST, STATUS_PORT))
+}
+
+/// State of the session, as served by the API.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct Snapshot {
+    /// Whether the client is connected to the hive.
+    pub connected: bool,
+    /// Round-trip time to the hive, if measured yet.
+    pub latency_ms: Option<u64>,
+    /// Nicknames of the searchers in the room, in joining order.
+    /// The host lists the clients of every room.
+    pub users: Vec<String>,
+    /// Open games, oldest first.
+    pub games: Vec<GameStatus>,
+    pub timer: TimerStatus,
+}
+
+/// Open game, as served by the API.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct GameStatus {
+    pub nickname: String,
+    pub address: String,
+    /// Milliseconds since the UNIX epoch.
+    pub opened_at: u64,
+}
+
+/// Hive timer, as served by the API.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(tag = "state", rename_all = "snake_case")]
+pub enum TimerStatus {
+    #[default]
+    Reset,
+    /// Start in milliseconds since the UNIX epoch, on the local clock.
+    Running { started_at: u64 },
+    Stopped { time_ms: u64 },
+}
+
+impl From<TimerView> for TimerStatus {
+    fn from(view: TimerView) -> Self {
+        match view {
+            TimerView::Reset => TimerStatus::Reset,
+            TimerView::Running(start) => TimerStatus::Running {
+                started_at: unix_millis(SystemTime::now() - start.elapsed()),
+            },
+            TimerView::Stopped(time) => TimerStatus::Stopped {
+                time_ms: time.as_millis() as u64,
+            },
+        }
+    }
+}
+
+/// Chat message, as streamed by the API.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ChatStatus {
+    pub nickname: String,
+    /// Milliseconds since the UNIX epoch.
+    pub sent_at: u64,
+    pub text: String,
+}
+
+impl From<ChatLine> for ChatStatus {
+    fn from(line: ChatLine) -> Self {
+        Self {
+            nickname: line.nickname,
+            sent_at: unix_millis(line.sent_at),
+            text: line.content.to_string(),
+        }
+    }
+}
+
+fn unix_millis(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH).unwrap_or_default().as_millis() as u64
+}
+
+/// Formats a server-sent event.
+fn sse(name: &str, data: &impl Serialize) -> String {
+    let data = serde_json::to_string(data).unwrap_or_default();
+    format!("event: {}\ndata: {}\n\n", name, data)
+}
+
+/// Keeps the snapshot up to date and streams changes to the subscribers.
+pub struct StatusObserver {
+    snapshot: Mutex<Snapshot>,
+    subscribers: Mutex<Vec<Sender<String>>>,
+    /// Set once the server reports its clients, the roster of its own client covers one room only.
+    host: AtomicBool,
+}
+
+impl StatusObserver {
+    pub fn new() -> Self {
+        Self {
+            snapshot: Mutex::new(Snapshot::default()),
+            subscribers: Mutex::new(Vec::new()),
+            host: AtomicBool::new(false),
+        }
+    }
+
+    pub fn snapshot(&self) -> Snapshot {
+        self.snapshot.lock().unwrap().clone()
+    }
+
+    /// Current snapshot as the first event, followed by all changes.
+    fn subscribe(&self) -> Receiver<String> {
+        let (sender, receiver) = bounded(SUBSCRIBER_QUEUE_SIZE);
+        // Holding the snapshot keeps changes from slipping in between.
+        let snapshot = self.snapshot.lock().unwrap();
+        sender.try_send(sse("snapshot", &*snapshot)).ok();
+        self.subscribers.lock().unwrap().push(sender);
+        receiver
+    }
+
+    /// Sends the event to every subscriber, forgets the ones that left or fell behind.
+    fn publish(&self, event: String) {
+        self.subscribers
+            .lock()
+            .unwrap()
+            .retain(|subscriber| subscriber.try_send(event.clone()).is_ok());
+    }
+}
+
+impl Default for StatusObserver {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SessionObserver for StatusObserver {
+    fn notify(&self, event: SessionEvent) {
+        let mut snapshot = self.snapshot.lock().unwrap();
+        let change = match event {
+            SessionEvent::Members(members) => {
+                self.host.store(true, Ordering::Relaxed);
+                snapshot.users = members.into_iter().map(|member| member.nickname).collect();
+                sse("users", &snapshot.users)
+            },
+            SessionEvent::Roster(members) if !self.host.load(Ordering::Relaxed) => {
+                let users: Vec<String> = members.into_iter().map(|member| member.nickname).collect();
+                // Resent on every game and latency change.
+                if users == snapshot.users {
+                    return
+                }
+                snapshot.users = users;
+                sse("users", &snapshot.users)
+            },
+            SessionEvent::LanGames(games) => {
+                snapshot.games = games
+                    .into_iter()
+                    .map(|game| GameStatus {
+                        nickname: game.nickname,
+                        address: game.address.to_string(),
+                        opened_at: unix_millis(game.opened_at),
+                    })
+                    .collect();
+                sse("games", &snapshot.games)
+            },
+            SessionEvent::Latency(latency) => {
+                snapshot.latency_ms = Some(latency.as_millis() as u64);
+                sse("latency", &snapshot.latency_ms)
+            },
+            SessionEvent::Connection(status) => {
+                snapshot.connected = matches!(status, ConnectionStatus::Connected);
+                sse("connection", &snapshot.connected)
+            },
+            SessionEvent::Timer(view) => {
+                snapshot.timer = view.into();
+                sse("timer", &snapshot.timer)
+            },
+            SessionEvent::Chat(line) => sse("chat", &ChatStatus::from(line)),
+            SessionEvent::Failed(reason) => {
+                snapshot.connected = false;
+                sse("failed", &reason)
+            },
+            SessionEvent::Listening(_)
+            | SessionEvent::MemberChanged(_)
+            | SessionEvent::Roster(_)
+            | SessionEvent::Hives(_) => return,
+        };
+        self.publish(change);
+    }
+}
+
+/// Serves the status until stopped, returns the address it listens on.
+pub async fn serve(
+    stop_token: Arc<PauseToken>,
+    address: SocketAddr,
+    status: Arc<StatusObserver>,
+) -> std::io::Result<SocketAddr> {
+    let listener = TcpListener::bind(address).await?;
+    let address = listener.local_addr()?;
+    info!("[status] serving on http://{}", address);
+    spawn(accept(stop_token, listener, status));
+    Ok(address)
+}
+
+/// Answers requests until stopped.
+async fn accept(stop_token: Arc<PauseToken>, listener: TcpListener, status: Arc<StatusObserver>) {
+    while stop_token.is_paused().await {
+        let incoming = listener.accept().fuse();
+        let stop = stop_token.wait().fuse();
+        pin_mut!(incoming);
+        pin_mut!(stop);
+        select! {
+            incoming = incoming => match incoming {
+                Ok((stream, peer)) => {
+                    debug!("[status] request from {}", peer);
+                    spawn(respond(stop_token.clone(), stream, status.clone()));
+                },
+                Err(error) => {
+                    warn!("[status] failed to accept: {}", error);
+                    break
+                },
+            },
+            _ = stop => break,
+        }
+    }
+    info!("[status] stopped");
+}
+
+/// Reads the request head, returns the method and the path without the query.
+async fn read_request(stream: &mut TcpStream) -> Option<(String, String)> {
+    let mut head = Vec::new();
+    let mut buffer = [0u8; 1024];
+    let read = async {
+        while !head.windows(4).any(|window| window == b"\r\n\r\n") {
+            let length = stream.read(&mut buffer).await.ok()?;
+            if length == 0 || head.len() + length > MAX_REQUEST_SIZE {
+                return None
+            }
+            head.extend_from_slice(&buffer[..length]);
+        }
+        Some(())
+    };
+    timeout(Duration::from_secs_f32(REQUEST_TIMEOUT), read).await.ok()??;
+    let head = String::from_utf8_lossy(&head);
+    let mut request_line = head.lines().next()?.split_whitespace();
+    let method = request_line.next()?.to_string();
+    let target = request_line.next()?;
+    let path = target.split('?').next()?.to_string();
+    Some((method, path))
+}
+
+/// Response with a complete body.
+fn response(status: &str, content_type: &str, body: &str) -> String {
+    format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nAccess-Control-Allow-Origin: *\r\nConnection: close\r\n\r\n{}",
+        status,
+        content_type,
+        body.len(),
+        body
+    )
+}
+
+async fn respond(stop_token: Arc<PauseToken>, mut stream: TcpStream, status: Arc<StatusObserver>) {
+    let reply = match read_request(&mut stream).await {
+        Some((method, _)) if method != "GET" => response("405 Method Not Allowed", "text/plain", "Only GET is supported."),
+        Some((_, path)) if path == "/status" => {
+            let body = serde_json::to_string(&status.snapshot()).unwrap_or_default();
+            response("200 OK", "application/json", &body)
+        },
+        Some((_, path)) if path == "/events" => {
+            stream_events(stop_token, &mut stream, status).await;
+            stream.shutdown(Shutdown::Both).ok();
+            return
+        },
+        Some(_) => response("404 Not Found", "text/plain", "Try /status or /events."),
+        None => response("400 Bad Request", "text/plain", "Malformed request."),
+    };
+    stream.write_all(reply.as_bytes()).await.ok();
+    stream.shutdown(Shutdown::Both).ok();
+}
+
+/// Streams changes until the client leaves or the API stops.
+async fn stream_events(stop_token: Arc<PauseToken>, stream: &mut TcpStream, status: Arc<StatusObserver>) {
+    let head = "HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nCache-Control: no-cache\r\nAccess-Control-Allow-Origin: *\r\nConnection: keep-alive\r\n\r\n";
+    if stream.write_all(head.as_bytes()).await.is_err() {
+        return
+    }
+    let events = status.subscribe();
+    let keep_alive = Duration::from_secs_f32(KEEP_ALIVE_INTERVAL);
+    let mut last_write = Instant::now();
+    while stop_token.is_paused().await {
+        let event = timeout(keep_alive.saturating_sub(last_write.elapsed()), events.recv()).fuse();
+        let stop = stop_token.wait().fuse();
+        pin_mut!(event);
+        pin_mut!(stop);
+        let chunk = select! {
+            event = event => match event {
+                Ok(Ok(event)) => event,
+                Ok(Err(_)) => break,
+                Err(_) => ": keep-alive\n\n".to_string(),
+            },
+            _ = stop => break,
+        };
+        if stream.write_all(chunk.as_bytes()).await.is_err() {
+            break
+        }
+        last_write = Instant::now();
+    }
+}
@@ -0,0 +1,122 @@
+//! Hive-wide timer.
+//!
+//! The server keeps the timer in its own clock, clients translate it to theirs.
+//! Clients estimate the offset between the clocks from request-reply round trips, like NTP.
+
+use std::collections::VecDeque;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+/// Number of round trips the offset is estimated from.
+const CLOCK_SAMPLES: usize = 8;
+
+/// Timer shared by the hive.
+/// Times are in microseconds of the server clock.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub enum TimerState {
+    Reset,
+    /// Carries the start time.
+    Running(i64),
+    /// Carries the final run time.
+    Stopped(i64),
+}
+
+impl TimerState {
+    /// Starts a new run, unless one is already running.
+    pub fn start(self, at: i64) -> Self {
+        match self {
+            TimerState::Running(_) => self,
+            _ => TimerState::Running(at),
+        }
+    }
+
+    /// Stops the run, if there is one.
+    pub fn stop(self, at: i64) -> Self {
+        match self {
+            TimerState::Running(start) => TimerState::Stopped((at - start).max(0)),
+            _ => self,
+        }
+    }
+}
+
+/// Timer translated to the local clock.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum TimerView {
+    #[default]
+    Reset,
+    /// Carries the local start time.
+    Running(Instant),
+    Stopped(Duration),
+}
+
+/// Current time in microseconds since the UNIX epoch.
+pub fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |time| time.as_micros() as i64)
+}
+
+/// Estimates the offset between the local and the server clock.
+pub struct ClockSync {
+    /// Round trip times and offsets of the recent samples.
+    samples: VecDeque<(i64, i64)>,
+    offset: i64,
+}
+
+impl ClockSync {
+    pub fn new() -> Self {
+        Self {
+            samples: VecDeque::with_capacity(CLOCK_SAMPLES),
+            offset: 0,
+        }
+    }
+
+    /// Adds a round trip, all times in microseconds.
+    /// `sent` and `received` are local, `server` is the time the server replied at.
+    /// Returns whether the offset estimate changed.
+    pub fn sample(&mut self, sent: i64, server: i64, received: i64) -> bool {
+        let round_trip = received - sent;
+        if round_trip < 0 {
+            return false
+        }
+        if self.samples.len() == CLOCK_SAMPLES {
+            self.samples.pop_front();
+        }
+        self.samples.push_back((round_trip, server - (sent + received) / 2));
+        // The shortest round trip has the least room for asymmetric delays.
+        let (_, offset) = *self.samples.iter().min().unwrap();
+        let changed = offset != self.offset;
+        self.offset = offset;
+        changed
+    }
+
+    /// Server clock minus the local clock, in microseconds.
+    pub fn offset(&self) -> i64 {
+        self.offset
+    }
+
+    /// Current time of the server clock.
+    pub fn server_now(&self) -> i64 {
+        now() + self.offset
+    }
+
+    /// Translates the timer to the local clock.
+    pub fn view(&self, state: TimerState) -> TimerView {
+        match state {
+            TimerState::Reset => TimerView::Reset,
+            TimerState::Running(start) => {
+                let elapsed = Duration::from_micros((self.server_now() - start).max(0) as u64);
+                let now = Instant::now();
+                TimerView::Running(now.checked_sub(elapsed).unwrap_or(now))
+            }
+            TimerState::Stopped(time) => TimerView::Stopped(Duration::from_micros(time.max(0) as u64)),
+        }
+    }
+}
+
+impl Default for ClockSync {
+    fn default() -> Self {
+        Self::new()
+    }
+}
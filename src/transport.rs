@@ -0,0 +1,246 @@
+//! Optional TLS layer under the message codec.
+//!
+//! - The host generates a self-signed certificate on first start.
+//! - Clients pin the certificate fingerprint on first connection (trust on first use).
+//! - No certificate authority is involved.
+
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fmt::{self, Display};
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+use async_std::net::{TcpListener, TcpStream};
+use futures::{AsyncRead, AsyncWrite};
+use futures_rustls::rustls::client::{ServerCertVerified, ServerCertVerifier};
+use futures_rustls::rustls::{self, Certificate, PrivateKey, ServerName};
+use futures_rustls::{TlsAcceptor, TlsConnector};
+use log::info;
+use sha2::{Digest, Sha256};
+use socket2::{Domain, Protocol, Socket, Type};
+
+/// Path of the host certificate.
+const CERTIFICATE_PATH: &str = "hive.cert";
+
+/// Path of the host private key.
+const KEY_PATH: &str = "hive.key";
+
+/// Path of the pinned hive fingerprints.
+const KNOWN_HIVES_PATH: &str = "known_hives";
+
+/// Name the certificate is issued for.
+/// Hives are recognized by fingerprint, so it is the same for all of them.
+const SERVER_NAME: &str = "hive-search";
+
+/// Byte stream the codec runs on.
+pub trait Io: AsyncRead + AsyncWrite + Send + Unpin {}
+
+impl<T: AsyncRead + AsyncWrite + Send + Unpin> Io for T {}
+
+/// Connection stream, encrypted or not.
+pub type Stream = Box<dyn Io>;
+
+/// Wraps an unencrypted connection.
+pub fn plain(stream: TcpStream) -> Stream {
+    Box::new(stream)
+}
+
+/// Pending connections the system keeps for a listener.
+const BACKLOG: i32 = 128;
+
+/// Listens on the address.
+/// The unspecified IPv6 address takes IPv4 connections too, whatever the system default is.
+pub fn listen(address: SocketAddr) -> io::Result<TcpListener> {
+    let socket = Socket::new(Domain::for_address(address), Type::STREAM, Some(Protocol::TCP))?;
+    if address.is_ipv6() && address.ip().is_unspecified() {
+        socket.set_only_v6(false)?;
+    }
+    // Same as the standard library, Windows would let others take over the port.
+    #[cfg(unix)]
+    socket.set_reuse_address(true)?;
+    socket.bind(&address.into())?;
+    socket.listen(BACKLOG)?;
+    let listener: std::net::TcpListener = socket.into();
+    Ok(TcpListener::from(listener))
+}
+
+/// IPv4 clients of a dual-stack listener show up with IPv4-mapped IPv6 addresses.
+/// Turns those back into IPv4, so every client has one address.
+pub fn unmapped(address: SocketAddr) -> SocketAddr {
+    match address.ip() {
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => SocketAddr::new(IpAddr::V4(ip), address.port()),
+            None => address,
+        },
+        IpAddr::V4(_) => address,
+    }
+}
+
+/// Creates the host acceptor.
+/// Generates the certificate on first use.
+pub fn acceptor() -> io::Result<TlsAcceptor> {
+    let (certificate, key) = load_or_generate_certificate()?;
+    info!("[tls] hive fingerprint {}", fingerprint(&certificate));
+    let config = rustls::ServerConfig::builder()
+        .with_safe_defaults()
+        .with_no_client_auth()
+        .with_single_cert(vec![Certificate(certificate)], PrivateKey(key))
+        .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))?;
+    Ok(TlsAcceptor::from(Arc::new(config)))
+}
+
+/// Encrypts an incoming connection.
+pub async fn accept(acceptor: &TlsAcceptor, stream: TcpStream) -> io::Result<Stream> {
+    let stream = acceptor.accept(stream).await?;
+    Ok(Box::new(stream))
+}
+
+/// Reasons for a failed encrypted connection.
+pub enum TlsError {
+    /// Hive presented another certificate than the pinned one.
+    /// Carries the hive address.
+    FingerprintMismatch(String),
+    HandshakeFailed,
+}
+
+impl Display for TlsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TlsError::FingerprintMismatch(hive) => write!(
+                f,
+                "Hive certificate changed since the last connection. If the host made a new one, remove '{}' from '{}'.",
+                hive, KNOWN_HIVES_PATH
+            ),
+            TlsError::HandshakeFailed => {
+                write!(f, "Failed to establish an encrypted connection, is the hive encrypted?")
+            }
+        }
+    }
+}
+
+/// Encrypts an outgoing connection.
+/// Pins the hive fingerprint if it is the first connection to `hive`.
+pub async fn connect(stream: TcpStream, hive: &str) -> Result<Stream, TlsError> {
+    let known_hives = load_known_hives();
+    let verifier = Arc::new(FingerprintVerifier {
+        pinned: known_hives.get(hive).cloned(),
+        seen: Mutex::new(None),
+    });
+    let config = rustls::ClientConfig::builder()
+        .with_safe_defaults()
+        .with_custom_certificate_verifier(verifier.clone())
+        .with_no_client_auth();
+    let server_name = ServerName::try_from(SERVER_NAME).unwrap();
+    let result = TlsConnector::from(Arc::new(config))
+        .connect(server_name, stream)
+        .await;
+    let seen = verifier.seen.lock().unwrap().clone();
+    match (result, &verifier.pinned, seen) {
+        (Ok(stream), None, Some(seen)) => {
+            info!("[tls] pinned {} as {}", hive, seen);
+            pin_hive(hive, &seen);
+            Ok(Box::new(stream))
+        }
+        (Ok(stream), _, _) => Ok(Box::new(stream)),
+        (Err(_), Some(pinned), Some(seen)) if *pinned != seen => {
+            Err(TlsError::FingerprintMismatch(hive.to_string()))
+        }
+        (Err(_), _, _) => Err(TlsError::HandshakeFailed),
+    }
+}
+
+/// Accepts only the pinned certificate, or any certificate if none is pinned.
+/// Handshake signatures are still verified against the certificate.
+struct FingerprintVerifier {
+    pinned: Option<String>,
+    seen: Mutex<Option<String>>,
+}
+
+impl ServerCertVerifier for FingerprintVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &Certificate,
+        _intermediates: &[Certificate],
+        _server_name: &ServerName,
+        _scts: &mut dyn Iterator<Item = &[u8]>,
+        _ocsp_response: &[u8],
+        _now: SystemTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        let seen = fingerprint(&end_entity.0);
+        *self.seen.lock().unwrap() = Some(seen.clone());
+        match &self.pinned {
+            Some(pinned) if *pinned != seen => Err(rustls::Error::General(
+                "hive certificate does not match the pinned fingerprint".to_string(),
+            )),
+            _ => Ok(ServerCertVerified::assertion()),
+        }
+    }
+}
+
+/// Formats SHA-256 of the certificate as colon separated hex.
+fn fingerprint(certificate: &[u8]) -> String {
+    Sha256::digest(certificate)
+        .iter()
+        .map(|byte| format!("{:02X}", byte))
+        .collect::<Vec<String>>()
+        .join(":")
+}
+
+/// Loads the host certificate and key in DER format.
+/// Generates and saves them if missing.
+fn load_or_generate_certificate() -> io::Result<(Vec<u8>, Vec<u8>)> {
+    if let (Ok(certificate), Ok(key)) = (fs::read(CERTIFICATE_PATH), fs::read(KEY_PATH)) {
+        return Ok((certificate, key));
+    }
+    info!("[tls] generating a new certificate");
+    let generated = rcgen::generate_simple_self_signed(vec![SERVER_NAME.to_string()])
+        .map_err(|error| io::Error::new(io::ErrorKind::Other, error))?;
+    let certificate = generated
+        .serialize_der()
+        .map_err(|error| io::Error::new(io::ErrorKind::Other, error))?;
+    let key = generated.serialize_private_key_der();
+    fs::write(CERTIFICATE_PATH, &certificate)?;
+    write_private(KEY_PATH, &key)?;
+    Ok((certificate, key))
+}
+
+/// Writes a file only the current user can read, where permissions allow it.
+fn write_private(path: &str, content: &[u8]) -> io::Result<()> {
+    let mut options = OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    options.open(path)?.write_all(content)
+}
+
+/// Loads pinned fingerprints.
+/// Each line holds a hive address and its fingerprint.
+fn load_known_hives() -> HashMap<String, String> {
+    let mut known_hives = HashMap::new();
+    if let Ok(content) = fs::read_to_string(KNOWN_HIVES_PATH) {
+        for line in content.lines() {
+            let mut parts = line.split_whitespace();
+            if let (Some(hive), Some(fingerprint)) = (parts.next(), parts.next()) {
+                known_hives.insert(hive.to_string(), fingerprint.to_string());
+            }
+        }
+    }
+    known_hives
+}
+
+/// Appends a fingerprint to the pinned ones.
+fn pin_hive(hive: &str, fingerprint: &str) {
+    if let Ok(mut file) = OpenOptions::new()
+        .append(true)
+        .create(true)
+        .open(KNOWN_HIVES_PATH)
+    {
+        writeln!(file, "{} {}", hive, fingerprint).ok();
+    }
+}
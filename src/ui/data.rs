@@ -1,16 +1,23 @@
-use std::{fs::{File, OpenOptions}, net::SocketAddr, path::Path, str::FromStr, sync::Arc};
+use std::{fs::{self, OpenOptions}, net::SocketAddr, path::Path, str::FromStr, sync::Arc, time::Duration};
 
 use serde::{Deserialize, Serialize};
 
 use druid::*;
 
-use crate::sync::PauseToken;
+use async_std::channel::Sender;
 
+use hive_search::{client::{self, ClientCommand}, discovery::Hive, heartbeat::HeartbeatConfig, lan_announcer::ShareMode, lan_listener::DetectionMode, messages::{ChatLine, MAX_NICKNAME_LENGTH, MAX_ROOM_LENGTH, Member}, server::ServerCommand, status, sync::PauseToken};
+
+use super::layouts::chat::{format_sent_at, format_server_time};
 use super::widgets::timer::TimerData;
 
 /// Path of the configuration file.
 const SETTINGS_PATH: &str = "config";
 
+/// Layout of the configuration file, written before the settings.
+/// Bump it whenever `Settings` changes and teach `load_settings` the previous layout.
+const SETTINGS_VERSION: u8 = 1;
+
 /// Relative path, from .minecraft to where servers NBT is stored.
 pub const SERVERS: &str = r"\servers.dat";
 
@@ -25,11 +32,76 @@ pub enum State {
     Client,
 }
 
+/// Where opened and closed worlds are noticed.
+#[derive(Clone, Copy, Data, PartialEq, Serialize, Deserialize)]
+pub enum Detection {
+    Logs,
+    Multicast,
+    Both,
+}
+
+impl From<Detection> for DetectionMode {
+    fn from(detection: Detection) -> Self {
+        match detection {
+            Detection::Logs => DetectionMode::Logs,
+            Detection::Multicast => DetectionMode::Multicast,
+            Detection::Both => DetectionMode::Both,
+        }
+    }
+}
+
+/// How hive games are shown in Minecraft.
+#[derive(Clone, Copy, Data, PartialEq, Serialize, Deserialize)]
+pub enum Sharing {
+    ServerList,
+    LanWorlds,
+}
+
+impl From<Sharing> for ShareMode {
+    fn from(sharing: Sharing) -> Self {
+        match sharing {
+            Sharing::ServerList => ShareMode::ServerList,
+            Sharing::LanWorlds => ShareMode::LanWorlds,
+        }
+    }
+}
+
+/// Which games a hosted hive shows when several are open.
+/// Priority takes the order from `Settings::priority`.
+#[derive(Clone, Copy, Data, PartialEq, Serialize, Deserialize)]
+pub enum Policy {
+    ShowAll,
+    FirstOpened,
+    MostRecent,
+    Priority,
+}
+
 /// Stores settings required for the Hive Search
 #[derive(Clone, Data, Lens, Serialize, Deserialize, PartialEq)]
 pub struct Settings {
     pub minecraft_path: String,
     pub server_addr: String,
+    pub nickname: String,
+    /// Room on the hive, empty for the default one.
+    pub room: String,
+    /// Room password, empty if the room is open.
+    pub password: String,
+    /// Encrypts the hive connection.
+    pub tls: bool,
+    pub detection: Detection,
+    pub sharing: Sharing,
+    /// Relays hosted games through the hive.
+    pub relay: bool,
+    pub policy: Policy,
+    /// Comma separated nicknames, preferred first.
+    pub priority: String,
+    /// Serves the session state to overlays and bots.
+    pub status_api: bool,
+    pub status_addr: String,
+    /// Seconds between heartbeats.
+    pub heartbeat_interval: f64,
+    /// Seconds of silence after which the peer is dropped.
+    pub heartbeat_timeout: f64,
 }
 
 impl Default for Settings {
@@ -37,6 +109,83 @@ impl Default for Settings {
         Self {
             minecraft_path: String::new(),
             server_addr: String::new(),
+            nickname: String::new(),
+            room: String::new(),
+            password: String::new(),
+            tls: false,
+            detection: Detection::Both,
+            sharing: Sharing::ServerList,
+            relay: false,
+            policy: Policy::ShowAll,
+            priority: String::new(),
+            status_api: false,
+            status_addr: status::default_address().to_string(),
+            heartbeat_interval: HeartbeatConfig::default().interval.as_secs_f64(),
+            heartbeat_timeout: HeartbeatConfig::default().timeout.as_secs_f64(),
+        }
+    }
+}
+
+/// Searcher connected to the hive.
+#[derive(Clone, Data, Lens)]
+pub struct RosterEntry {
+    pub id: u64,
+    pub nickname: String,
+    pub ip: String,
+    /// Joining time as UTC `HH:MM`.
+    pub since: String,
+    /// Port of the open game.
+    pub hosting: Option<u16>,
+    /// Round-trip time to the hive, in milliseconds.
+    pub latency: Option<u64>,
+    /// Runs the hive.
+    pub host: bool,
+}
+
+impl From<Member> for RosterEntry {
+    fn from(member: Member) -> Self {
+        Self {
+            id: member.id,
+            nickname: member.nickname,
+            ip: member.ip.to_string(),
+            since: format_server_time(member.connected_since),
+            hosting: member.hosting,
+            latency: member.latency.map(|latency| latency.as_millis() as u64),
+            host: member.host,
+        }
+    }
+}
+
+/// Line of the hive chat.
+#[derive(Clone, Data, Lens)]
+pub struct ChatEntry {
+    pub time: String,
+    pub nickname: String,
+    pub text: String,
+}
+
+impl From<ChatLine> for ChatEntry {
+    fn from(line: ChatLine) -> Self {
+        Self {
+            time: format_sent_at(&line),
+            nickname: line.nickname,
+            text: line.content.to_string(),
+        }
+    }
+}
+
+/// Hive found on the local network.
+#[derive(Clone, Data, Lens)]
+pub struct HiveEntry {
+    pub name: String,
+    pub address: String,
+}
+
+impl From<Hive> for HiveEntry {
+    fn from(hive: Hive) -> Self {
+        Self {
+            name: hive.name,
+            address: hive.address.to_string(),
         }
     }
 }
@@ -48,7 +197,22 @@ pub struct AppData {
     pub settings: Settings,
     pub stop_token: Option<Arc<PauseToken>>,
     pub pause_token: Option<Arc<PauseToken>>,
+    /// Requests for the running client.
+    pub commands: Option<Arc<Sender<ClientCommand>>>,
+    /// Requests for the hosted server.
+    pub server_commands: Option<Arc<Sender<ServerCommand>>>,
+    /// Clients of the hosted server.
+    pub roster: Arc<Vec<RosterEntry>>,
+    /// Searchers in the room, as seen by the client.
+    pub searchers: Arc<Vec<RosterEntry>>,
+    /// Hives announced on the local network.
+    pub hives: Arc<Vec<HiveEntry>>,
+    /// Hive chat, oldest first.
+    pub chat: Arc<Vec<ChatEntry>>,
+    pub chat_input: String,
     pub timer: TimerData,
+    /// Reason why the last session ended, if it failed.
+    pub error: String,
     pub void: String,
 }
 
@@ -59,7 +223,15 @@ impl Default for AppData {
             settings: Settings::default(),
             stop_token: None,
             pause_token: None,
+            commands: None,
+            server_commands: None,
+            roster: Arc::new(Vec::new()),
+            searchers: Arc::new(Vec::new()),
+            hives: Arc::new(Vec::new()),
+            chat: Arc::new(Vec::new()),
+            chat_input: String::new(),
             timer: TimerData::default(),
+            error: String::new(),
             void: "a".to_owned(),
         }
     }
@@ -68,20 +240,41 @@ impl Default for AppData {
 /// All possible validation errors.
 pub enum SettingsValidationError {
     InvalidAddr,
+    /// Hosting needs an IP to listen on.
+    InvalidHostAddr,
+    InvalidNickname,
+    InvalidRoom,
     MissingLogs,
     MissingServers,
+    InvalidStatusAddr,
+    /// Interval has to be positive and shorter than the timeout.
+    InvalidHeartbeat,
 }
 
-/// Confirms validity of the server address.
+/// Confirms validity of the server address, nickname, room code, status API address and heartbeat.
 /// Confirms existence of latest.log and servers.dat files.
 ///
 /// DOES NOT confirm existence of server under the address.
 ///
 /// DOES NOT confirm validity of latest.log and servers.dat files.
 pub fn validate_settings(settings: &Settings) -> Result<(), SettingsValidationError> {
-    if let Err(_) = SocketAddr::from_str(&settings.server_addr) {
+    if !client::is_valid_address(&settings.server_addr) {
         return Err(SettingsValidationError::InvalidAddr);
     }
+    if settings.nickname.chars().count() > MAX_NICKNAME_LENGTH || settings.nickname.contains('§') {
+        return Err(SettingsValidationError::InvalidNickname);
+    }
+    if settings.room.trim().chars().count() > MAX_ROOM_LENGTH || settings.room.contains('§') {
+        return Err(SettingsValidationError::InvalidRoom);
+    }
+    if settings.status_api && SocketAddr::from_str(&settings.status_addr).is_err() {
+        return Err(SettingsValidationError::InvalidStatusAddr);
+    }
+    if !(settings.heartbeat_interval > 0. && settings.heartbeat_interval < settings.heartbeat_timeout)
+        || Duration::try_from_secs_f64(settings.heartbeat_timeout).is_err()
+    {
+        return Err(SettingsValidationError::InvalidHeartbeat);
+    }
     let logs = settings.minecraft_path.clone() + LATEST_LOG;
     if !Path::new(&logs).exists() {
         return Err(SettingsValidationError::MissingLogs);
@@ -93,6 +286,15 @@ pub fn validate_settings(settings: &Settings) -> Result<(), SettingsValidationEr
     Ok(())
 }
 
+/// Same as `validate_settings`, the address also has to be an IP.
+pub fn validate_host_settings(settings: &Settings) -> Result<(), SettingsValidationError> {
+    validate_settings(settings)?;
+    if SocketAddr::from_str(&settings.server_addr).is_err() {
+        return Err(SettingsValidationError::InvalidHostAddr);
+    }
+    Ok(())
+}
+
 /// Saves settings in the SETTINGS_PATH.
 pub fn save_settings(settings: &Settings) {
     if let Ok(file) = OpenOptions::new()
@@ -101,18 +303,31 @@ pub fn save_settings(settings: &Settings) {
         .create(true)
         .open(SETTINGS_PATH)
     {
-        bincode::serialize_into(file, settings).expect("Failed to serialize settings into a file.");
+        bincode::serialize_into(file, &(SETTINGS_VERSION, settings)).expect("Failed to serialize settings into a file.");
         return
     }
 }
 
 /// Loads settings from SETTINGS_PATH.
+/// Files from before the versioning keep the Minecraft path and the server address.
 /// If failed at any step, returns default settings.
 pub fn load_settings() -> Settings {
-    if let Ok(file) = File::open(SETTINGS_PATH) {
-        if let Ok(settings) = bincode::deserialize_from::<File, Settings>(file) {
+    let bytes = match fs::read(SETTINGS_PATH) {
+        Ok(bytes) => bytes,
+        Err(_) => return Settings::default(),
+    };
+    if let Some((&SETTINGS_VERSION, rest)) = bytes.split_first() {
+        if let Ok(settings) = bincode::deserialize::<Settings>(rest) {
             return settings;
         }
     }
+    // Every unversioned layout started with these two.
+    if let Ok((minecraft_path, server_addr)) = bincode::deserialize::<(String, String)>(&bytes) {
+        return Settings {
+            minecraft_path,
+            server_addr,
+            ..Settings::default()
+        };
+    }
     Settings::default()
 }
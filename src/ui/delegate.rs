@@ -1,9 +1,18 @@
+use std::sync::Arc;
+
 use async_std::task::block_on;
 use druid::*;
 
+use hive_search::server::ServerCommand;
+
 use super::data::{AppData, State};
+use super::layouts::chat::{CHAT, CHAT_HISTORY};
+use super::layouts::client::ROSTER;
+use super::layouts::config::{HIVES, SELECT_HIVE};
+use super::layouts::host::{BAN, KICK, MEMBERS, MEMBER_CHANGED};
+use super::widgets::timer::TIMER;
 
-pub const RUNTIME_ERROR: Selector<()> = Selector::new("runtime-error");
+pub const RUNTIME_ERROR: Selector<String> = Selector::new("runtime-error");
 
 pub struct Delegate;
 
@@ -20,12 +29,64 @@ impl AppDelegate<AppData> for Delegate {
             data.settings.minecraft_path = file_info.path().to_str().unwrap().to_string();
             return Handled::Yes;
         }
-        if let Some(()) = cmd.get(RUNTIME_ERROR) {
+        if let Some(error) = cmd.get(RUNTIME_ERROR) {
             data.state = State::Config;
+            data.error = error.clone();
             if let Some(stop_token) = &mut data.stop_token {
                 block_on(stop_token.resume());
                 data.stop_token = None;
                 data.pause_token = None;
+                data.commands = None;
+                data.server_commands = None;
+                data.roster = Arc::new(Vec::new());
+                data.searchers = Arc::new(Vec::new());
+            }
+            return Handled::Yes;
+        }
+        if let Some(view) = cmd.get(TIMER) {
+            data.timer.view = *view;
+            return Handled::Yes;
+        }
+        if let Some(hives) = cmd.get(HIVES) {
+            data.hives = Arc::new(hives.iter().cloned().map(Into::into).collect());
+            return Handled::Yes;
+        }
+        if let Some(address) = cmd.get(SELECT_HIVE) {
+            data.settings.server_addr = address.clone();
+            return Handled::Yes;
+        }
+        if let Some(line) = cmd.get(CHAT) {
+            let chat = Arc::make_mut(&mut data.chat);
+            chat.push(line.clone().into());
+            if chat.len() > CHAT_HISTORY {
+                chat.remove(0);
+            }
+            return Handled::Yes;
+        }
+        if let Some(members) = cmd.get(MEMBERS) {
+            data.roster = Arc::new(members.iter().cloned().map(Into::into).collect());
+            return Handled::Yes;
+        }
+        if let Some(member) = cmd.get(MEMBER_CHANGED) {
+            let roster = Arc::make_mut(&mut data.roster);
+            if let Some(entry) = roster.iter_mut().find(|entry| entry.id == member.id) {
+                *entry = member.clone().into();
+            }
+            return Handled::Yes;
+        }
+        if let Some(members) = cmd.get(ROSTER) {
+            data.searchers = Arc::new(members.iter().cloned().map(Into::into).collect());
+            return Handled::Yes;
+        }
+        if let Some(id) = cmd.get(KICK) {
+            if let Some(server_commands) = &data.server_commands {
+                server_commands.try_send(ServerCommand::Kick(*id)).ok();
+            }
+            return Handled::Yes;
+        }
+        if let Some(id) = cmd.get(BAN) {
+            if let Some(server_commands) = &data.server_commands {
+                server_commands.try_send(ServerCommand::Ban(*id)).ok();
             }
             return Handled::Yes;
         }
@@ -0,0 +1,150 @@
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use druid::widget::*;
+use druid::*;
+
+use hive_search::client::ClientCommand;
+use hive_search::messages::{ChatContent, ChatLine, MAX_CHAT_LENGTH, QuickPing};
+
+use crate::ui::widgets::my_widget_ext::MyWidgetExt;
+use crate::ui::widgets::wrappers::new_button;
+
+use super::super::data::*;
+use super::consts::*;
+
+pub const CHAT: Selector<ChatLine> = Selector::new("chat");
+
+/// Lines kept in the panel, older ones scroll away for good.
+pub const CHAT_HISTORY: usize = 100;
+
+/*
+Hive chat.
+Messages, quick pings and the input line.
+*/
+pub fn chat() -> impl Widget<AppData> {
+    Flex::column()
+        .with_flex_child(chat_log(), 4.)
+        .with_spacer(SPACER_SIZE)
+        .with_flex_child(quick_pings(), 1.)
+        .with_spacer(SPACER_SIZE)
+        .with_flex_child(chat_input(), 1.)
+}
+
+fn chat_log() -> impl Widget<AppData> {
+    List::new(|| {
+        Label::new(|entry: &ChatEntry, _env: &Env| format!("[{}] {}: {}", entry.time, entry.nickname, entry.text))
+            .with_line_break_mode(LineBreaking::WordWrap)
+            .expand_width()
+    })
+    .scroll()
+    .vertical()
+    .controller(FollowNewLines::default())
+    .background(Color::rgb8(0x90, 0x90, 0xFF))
+    .expand()
+    .lens(AppData::chat)
+}
+
+/*
+Keeps the chat log scrolled to the newest line.
+Scrolls on the next timer tick, once the new line is laid out.
+*/
+#[derive(Default)]
+struct FollowNewLines {
+    timer: Option<TimerToken>,
+}
+
+type ChatLog = Arc<Vec<ChatEntry>>;
+
+impl<W: Widget<ChatLog>> Controller<ChatLog, Scroll<ChatLog, W>> for FollowNewLines {
+    fn event(&mut self, child: &mut Scroll<ChatLog, W>, ctx: &mut EventCtx, event: &Event, data: &mut ChatLog, env: &Env) {
+        match event {
+            Event::Timer(token) if Some(*token) == self.timer => {
+                self.timer = None;
+                let height = child.child_size().height;
+                child.scroll_by(ctx, Vec2::new(0., height));
+            }
+            _ => child.event(ctx, event, data, env),
+        }
+    }
+
+    fn update(&mut self, child: &mut Scroll<ChatLog, W>, ctx: &mut UpdateCtx, old_data: &ChatLog, data: &ChatLog, env: &Env) {
+        if !old_data.same(data) {
+            self.timer = Some(ctx.request_timer(Duration::from_millis(0)));
+        }
+        child.update(ctx, old_data, data, env);
+    }
+}
+
+fn quick_pings() -> impl Widget<AppData> {
+    let mut row = Flex::row();
+    for (index, ping) in QuickPing::ALL.iter().enumerate() {
+        if index > 0 {
+            row.add_spacer(SPACER_SIZE);
+        }
+        row.add_flex_child(quick_ping(*ping), 1.);
+    }
+    row
+}
+
+fn quick_ping(ping: QuickPing) -> impl Widget<AppData> {
+    new_button::<AppData>(&ping.to_string())
+        .on_click(move |_event, data, _env| send(data, ChatContent::Ping(ping)))
+        .expand()
+}
+
+fn chat_input() -> impl Widget<AppData> {
+    Flex::row()
+        .with_flex_child(TextBox::new().lens(AppData::chat_input).expand(), 4.)
+        .with_spacer(SPACER_SIZE)
+        .with_child(chat_length())
+        .with_spacer(SPACER_SIZE)
+        .with_flex_child(
+            new_button::<AppData>("Send")
+                .on_click(|_event, data, _env| {
+                    let text = data.chat_input.trim().to_string();
+                    if !text.is_empty() && !too_long(data) {
+                        send(data, ChatContent::Text(text));
+                        data.chat_input.clear();
+                    }
+                })
+                .expand(),
+            1.,
+        )
+}
+
+/*
+Length of the typed message against the limit.
+Marked red once the hive would refuse it.
+*/
+fn chat_length() -> impl Widget<AppData> {
+    let length = |data: &AppData, _env: &Env| format!("{}/{}", data.chat_input.trim().chars().count(), MAX_CHAT_LENGTH);
+    Either::new(
+        |data: &AppData, _env| too_long(data),
+        Label::new(length)
+            .background(Color::rgb8(0xFF, 0x90, 0x90))
+            .with_tooltip("Too long to send, shorten the message."),
+        Label::new(length),
+    )
+}
+
+fn too_long(data: &AppData) -> bool {
+    data.chat_input.trim().chars().count() > MAX_CHAT_LENGTH
+}
+
+fn send(data: &AppData, content: ChatContent) {
+    if let Some(commands) = &data.commands {
+        commands.try_send(ClientCommand::Chat(content)).ok();
+    }
+}
+
+/// Server time of the message as UTC `HH:MM`, the same for every searcher.
+pub fn format_sent_at(line: &ChatLine) -> String {
+    format_server_time(line.sent_at)
+}
+
+/// Server time as UTC `HH:MM`.
+pub fn format_server_time(time: SystemTime) -> String {
+    let seconds = time.duration_since(UNIX_EPOCH).map(|time| time.as_secs()).unwrap_or(0);
+    format!("{:02}:{:02}", seconds / 3600 % 24, seconds / 60 % 60)
+}
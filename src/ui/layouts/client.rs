@@ -1,19 +1,41 @@
+use std::sync::Arc;
+use std::time::Duration;
+
 use async_std::task::block_on;
 use druid::widget::*;
 use druid::*;
 
+use hive_search::client::{ClientCommand, ConnectionStatus};
+use hive_search::messages::Member;
+
 use crate::ui::widgets::dyn_label::DynLabel;
 use crate::ui::widgets::wrappers::{new_button, new_label};
 
+use super::chat::chat;
 use super::super::data::*;
 use super::consts::*;
 
-pub const LAN_COUNT: Selector<u8> = Selector::new("lan-count");
+pub const LAN_COUNT: Selector<usize> = Selector::new("lan-count");
+pub const LATENCY: Selector<Duration> = Selector::new("latency");
+pub const CONNECTION_STATUS: Selector<ConnectionStatus> = Selector::new("connection-status");
+pub const ROSTER: Selector<Vec<Member>> = Selector::new("roster");
 
 pub fn client_status() -> impl Widget<AppData> {
     Flex::column()
         .with_flex_child(new_label("Status:").expand(), 1.)
         .with_spacer(SPACER_SIZE)
+        .with_flex_child(
+            DynLabel::new(
+                connection_status_to_string(&ConnectionStatus::Connected),
+                CONNECTION_STATUS,
+                connection_status_to_string,
+            )
+            .align_horizontal(UnitPoint::CENTER)
+            .background(Color::rgb8(0x90, 0x90, 0xFF))
+            .expand(),
+            1.,
+        )
+        .with_spacer(SPACER_SIZE)
         .with_flex_child(
             DynLabel::new(lan_count_to_string(&0), LAN_COUNT, lan_count_to_string)
                 .align_horizontal(UnitPoint::CENTER)
@@ -21,6 +43,53 @@ pub fn client_status() -> impl Widget<AppData> {
                 .expand(),
             1.,
         )
+        .with_spacer(SPACER_SIZE)
+        .with_flex_child(
+            DynLabel::new("Ping: -".to_string(), LATENCY, latency_to_string)
+                .align_horizontal(UnitPoint::CENTER)
+                .background(Color::rgb8(0x90, 0x90, 0xFF))
+                .expand(),
+            1.,
+        )
+}
+
+/*
+Searchers in the room, in joining order.
+*/
+fn searchers() -> impl Widget<AppData> {
+    List::new(|| {
+        Label::new(|entry: &RosterEntry, _env: &Env| searcher_to_string(entry))
+            .align_horizontal(UnitPoint::CENTER)
+            .background(Color::rgb8(0x90, 0x90, 0xFF))
+            .expand_width()
+    })
+    .with_spacing(SPACER_SIZE)
+    .scroll()
+    .vertical()
+    .lens(AppData::searchers)
+}
+
+/*
+Hive timer buttons.
+Pressing any of them changes the timer of every searcher.
+*/
+pub fn timer_controls() -> impl Widget<AppData> {
+    Flex::row()
+        .with_flex_child(timer_button("Start", ClientCommand::TimerStart), 1.)
+        .with_spacer(SPACER_SIZE)
+        .with_flex_child(timer_button("Stop", ClientCommand::TimerStop), 1.)
+        .with_spacer(SPACER_SIZE)
+        .with_flex_child(timer_button("Reset", ClientCommand::TimerReset), 1.)
+}
+
+fn timer_button(text: &str, command: ClientCommand) -> impl Widget<AppData> {
+    new_button::<AppData>(text)
+        .on_click(move |_event, data, _env| {
+            if let Some(commands) = &data.commands {
+                commands.try_send(command.clone()).ok();
+            }
+        })
+        .expand()
 }
 
 pub fn client() -> impl Widget<AppData> {
@@ -33,20 +102,54 @@ pub fn client() -> impl Widget<AppData> {
                         block_on(stop_token.resume());
                         data.stop_token = None;
                         data.pause_token = None;
+                        data.commands = None;
+                        data.searchers = Arc::new(Vec::new());
                     }
                 })
                 .expand(),
             1.,
         )
         .with_spacer(SPACER_SIZE)
+        .with_flex_child(timer_controls(), 1.)
+        .with_spacer(SPACER_SIZE)
         .with_flex_child(client_status(), 1.)
+        .with_spacer(SPACER_SIZE)
+        .with_flex_child(searchers(), 2.)
+        .with_spacer(SPACER_SIZE)
+        .with_flex_child(chat(), 3.)
         .padding(SPACER_SIZE)
 }
 
-fn lan_count_to_string(count: &u8) -> String {
+fn lan_count_to_string(count: &usize) -> String {
     match count {
         0 => "No games.".to_string(),
         1 => "One game.".to_string(),
-        _ => "Many games.".to_string(),
+        _ => count.to_string() + " games.",
+    }
+}
+
+fn latency_to_string(latency: &Duration) -> String {
+    format!("Ping: {} ms", latency.as_millis())
+}
+
+pub fn searcher_to_string(entry: &RosterEntry) -> String {
+    let mut text = format!("{} ({}), since {}", entry.nickname, entry.ip, entry.since);
+    if entry.host {
+        text += ", runs the hive";
+    }
+    if let Some(port) = entry.hosting {
+        text += &format!(", hosting on {}", port);
+    }
+    if let Some(latency) = entry.latency {
+        text += &format!(", {} ms", latency);
+    }
+    text
+}
+
+fn connection_status_to_string(status: &ConnectionStatus) -> String {
+    match status {
+        ConnectionStatus::Connected => "Connected.".to_string(),
+        ConnectionStatus::Reconnecting(0) => "Reconnecting...".to_string(),
+        ConnectionStatus::Reconnecting(attempts) => format!("Reconnecting... ({} failed)", attempts),
     }
 }
@@ -1,26 +1,66 @@
 use std::net::SocketAddr;
 use std::str::FromStr;
 use std::sync::Arc;
+use std::time::Duration;
 
+use async_std::channel::unbounded;
+use async_std::task::spawn;
 use druid::widget::*;
+use druid::text::ParseFormatter;
 use druid::*;
+use log::warn;
 
-use crate::assets::ServerIcons;
-use crate::client;
-use crate::server;
-use crate::sync::PauseToken;
+use hive_search::assets::ServerIcons;
+use hive_search::client::{self, ClientConfig};
+use hive_search::discovery::Hive;
+use hive_search::heartbeat::HeartbeatConfig;
+use hive_search::observer::{FanOutObserver, Observer};
+use hive_search::server::{self, HostPolicy, ServerConfig};
+use hive_search::status::{self, StatusObserver};
+use hive_search::sync::PauseToken;
+
+use crate::ui::observer::DruidObserver;
+use crate::ui::widgets::my_widget_ext::MyWidgetExt;
 use crate::ui::widgets::timer_config::TimerConfig;
-use crate::ui::widgets::wrappers::{new_button, new_label};
+use crate::ui::widgets::wrappers::{new_button, new_dynamic_button, new_label};
 
 use super::super::data::*;
 use super::consts::*;
 
+pub const HIVES: Selector<Vec<Hive>> = Selector::new("hives");
+pub const SELECT_HIVE: Selector<String> = Selector::new("select-hive");
+
 /*
 Configuration menu.
 */
 pub fn config() -> impl Widget<AppData> {
     //Flex::column()
+    //    .with_child(error_label())
     //    .with_flex_child(address_input().expand_height(), 1.)
+    //    .with_spacer(SPACER_SIZE)
+    //    .with_flex_child(discovered_hives().expand_height(), 2.)
+    //    .with_spacer(SPACER_SIZE)
+    //    .with_flex_child(nickname_input().expand_height(), 1.)
+    //    .with_spacer(SPACER_SIZE)
+    //    .with_flex_child(room_input().expand_height(), 1.)
+    //    .with_spacer(SPACER_SIZE)
+    //    .with_flex_child(password_input().expand_height(), 1.)
+    //    .with_spacer(SPACER_SIZE)
+    //    .with_flex_child(encryption_toggle().expand_height(), 1.)
+    //    .with_spacer(SPACER_SIZE)
+    //    .with_flex_child(relay_toggle().expand_height(), 1.)
+    //    .with_spacer(SPACER_SIZE)
+    //    .with_flex_child(policy_select().expand_height(), 1.)
+    //    .with_spacer(SPACER_SIZE)
+    //    .with_flex_child(priority_input().expand_height(), 1.)
+    //    .with_spacer(SPACER_SIZE)
+    //    .with_flex_child(detection_select().expand_height(), 1.)
+    //    .with_spacer(SPACER_SIZE)
+    //    .with_flex_child(sharing_select().expand_height(), 1.)
+    //    .with_spacer(SPACER_SIZE)
+    //    .with_flex_child(status_input().expand_height(), 1.)
+    //    .with_spacer(SPACER_SIZE)
+    //    .with_flex_child(heartbeat_input().expand_height(), 1.)
     //    .with_spacer(SPACER_SIZE * 2.)
     //    .with_flex_child(minecraft_browser_top().expand_height(), 1.)
     //    .with_spacer(SPACER_SIZE)
@@ -30,7 +70,28 @@ pub fn config() -> impl Widget<AppData> {
     //    .with_spacer(SPACER_SIZE * 2.)
     //    .with_flex_child(TimerConfig::new().lens(AppData::timer).expand_height(), 6.)
     //    .padding(SPACER_SIZE);
-    TimerConfig::new().lens(AppData::timer)
+    Flex::column()
+        .with_child(error_label())
+        .with_flex_child(address_input().expand_height(), 1.)
+        .with_spacer(SPACER_SIZE)
+        .with_flex_child(discovered_hives().expand_height(), 2.)
+        .with_child(TimerConfig::new().lens(AppData::timer))
+}
+
+/*
+Reason why the last session failed.
+Hidden if there is none.
+*/
+fn error_label() -> impl Widget<AppData> {
+    Either::new(
+        |data: &AppData, _env| data.error.is_empty(),
+        SizedBox::empty(),
+        Label::new(|data: &AppData, _env: &Env| data.error.clone())
+            .with_line_break_mode(LineBreaking::WordWrap)
+            .align_horizontal(UnitPoint::CENTER)
+            .background(Color::rgb8(0xFF, 0x90, 0x90))
+            .expand_width(),
+    )
 }
 
 /*
@@ -49,11 +110,263 @@ fn address_input() -> impl Widget<AppData> {
         .with_spacer(SPACER_SIZE)
         .with_flex_child(
             TextBox::new()
+                .with_placeholder("hive.example.lan:2137")
                 .lens(Settings::server_addr)
                 .lens(AppData::settings)
                 .expand(),
             1.,
         )
+        .with_tooltip("IP or hostname with the port, IPv6 addresses go in brackets. Hosting needs an IP, [::] takes both IPv4 and IPv6.")
+}
+
+/*
+Hives announced on the local network.
+Clicking one fills the host address.
+*/
+fn discovered_hives() -> impl Widget<AppData> {
+    Either::new(
+        |data: &AppData, _env| data.hives.is_empty(),
+        new_label("No hives nearby"),
+        List::new(hive_entry)
+            .with_spacing(SPACER_SIZE)
+            .scroll()
+            .vertical()
+            .lens(AppData::hives),
+    )
+}
+
+fn hive_entry() -> impl Widget<HiveEntry> {
+    new_dynamic_button(|entry: &HiveEntry, _env: &Env| format!("{} ({})", entry.name, entry.address))
+        .on_click(|ctx, entry, _env| ctx.submit_command(SELECT_HIVE.with(entry.address.clone())))
+        .expand_width()
+}
+
+/*
+Nickname input.
+Title and input field.
+*/
+fn nickname_input() -> impl Widget<AppData> {
+    Flex::row()
+        .with_child(
+            Label::new("Nickname")
+                .padding(Insets::uniform_xy(5., 0.))
+                .align_horizontal(UnitPoint::CENTER)
+                .background(Color::rgb8(0x90, 0x90, 0xFF))
+                .expand_height(),
+        )
+        .with_spacer(SPACER_SIZE)
+        .with_flex_child(
+            TextBox::new()
+                .lens(Settings::nickname)
+                .lens(AppData::settings)
+                .expand(),
+            1.,
+        )
+}
+
+/*
+Room code input.
+Title and input field.
+*/
+fn room_input() -> impl Widget<AppData> {
+    Flex::row()
+        .with_child(
+            Label::new("Room code")
+                .padding(Insets::uniform_xy(5., 0.))
+                .align_horizontal(UnitPoint::CENTER)
+                .background(Color::rgb8(0x90, 0x90, 0xFF))
+                .expand_height(),
+        )
+        .with_spacer(SPACER_SIZE)
+        .with_flex_child(
+            TextBox::new()
+                .with_placeholder("leave empty for the default room")
+                .lens(Settings::room)
+                .lens(AppData::settings)
+                .expand(),
+            1.,
+        )
+        .with_tooltip("Teams sharing a hive pick different codes, rooms never see each other's games.")
+}
+
+/*
+Room password input.
+Title and input field.
+*/
+fn password_input() -> impl Widget<AppData> {
+    Flex::row()
+        .with_child(
+            Label::new("Room password")
+                .padding(Insets::uniform_xy(5., 0.))
+                .align_horizontal(UnitPoint::CENTER)
+                .background(Color::rgb8(0x90, 0x90, 0xFF))
+                .expand_height(),
+        )
+        .with_spacer(SPACER_SIZE)
+        .with_flex_child(
+            TextBox::new()
+                .lens(Settings::password)
+                .lens(AppData::settings)
+                .expand(),
+            1.,
+        )
+}
+
+/*
+Connection encryption toggle.
+Has to match between the host and clients.
+*/
+fn encryption_toggle() -> impl Widget<AppData> {
+    Checkbox::new("Encrypt connection")
+        .lens(Settings::tls)
+        .lens(AppData::settings)
+        .with_tooltip("Encrypts the hive traffic. The host and all clients need the same setting.")
+        .align_horizontal(UnitPoint::CENTER)
+}
+
+/*
+Relay toggle, only used when hosting.
+*/
+fn relay_toggle() -> impl Widget<AppData> {
+    Checkbox::new("Relay games")
+        .lens(Settings::relay)
+        .lens(AppData::settings)
+        .with_tooltip("Games are reached through the hive, nobody has to forward ports. All game traffic passes through the hive.")
+        .align_horizontal(UnitPoint::CENTER)
+}
+
+/*
+Host policy selection, only used when hosting.
+*/
+fn policy_select() -> impl Widget<AppData> {
+    Flex::row()
+        .with_child(new_label("Show games"))
+        .with_spacer(SPACER_SIZE)
+        .with_child(
+            RadioGroup::new(vec![
+                ("All", Policy::ShowAll),
+                ("First opened", Policy::FirstOpened),
+                ("Most recent", Policy::MostRecent),
+                ("By priority", Policy::Priority),
+            ])
+            .lens(Settings::policy)
+            .lens(AppData::settings),
+        )
+        .with_tooltip("Which games the hive shows when several searchers open one. The rest wait for their turn.")
+}
+
+/*
+Nickname priority, used by the priority policy.
+*/
+fn priority_input() -> impl Widget<AppData> {
+    Flex::row()
+        .with_child(
+            Label::new("Priority")
+                .padding(Insets::uniform_xy(5., 0.))
+                .align_horizontal(UnitPoint::CENTER)
+                .background(Color::rgb8(0x90, 0x90, 0xFF))
+                .expand_height(),
+        )
+        .with_spacer(SPACER_SIZE)
+        .with_flex_child(
+            TextBox::new()
+                .with_placeholder("Nicknames, separated by commas")
+                .lens(Settings::priority)
+                .lens(AppData::settings)
+                .expand(),
+            1.,
+        )
+}
+
+/*
+Detection mode selection.
+Logs break across versions and mod loaders, LAN announcements cover those.
+*/
+fn detection_select() -> impl Widget<AppData> {
+    Flex::row()
+        .with_child(new_label("Detect worlds from"))
+        .with_spacer(SPACER_SIZE)
+        .with_child(
+            RadioGroup::new(vec![
+                ("Logs", Detection::Logs),
+                ("LAN announcements", Detection::Multicast),
+                ("Both", Detection::Both),
+            ])
+            .lens(Settings::detection)
+            .lens(AppData::settings),
+        )
+        .with_tooltip("How HiveSearch notices that you opened your world to LAN.")
+}
+
+/*
+Share mode selection.
+*/
+fn sharing_select() -> impl Widget<AppData> {
+    Flex::row()
+        .with_child(new_label("Show games as"))
+        .with_spacer(SPACER_SIZE)
+        .with_child(
+            RadioGroup::new(vec![
+                ("Servers", Sharing::ServerList),
+                ("LAN worlds", Sharing::LanWorlds),
+            ])
+            .lens(Settings::sharing)
+            .lens(AppData::settings),
+        )
+        .with_tooltip("Servers need a refresh of the multiplayer screen, LAN worlds show up by themselves.")
+}
+
+/*
+Status API toggle and address.
+*/
+fn status_input() -> impl Widget<AppData> {
+    Flex::row()
+        .with_child(
+            Checkbox::new("Status API")
+                .lens(Settings::status_api)
+                .lens(AppData::settings),
+        )
+        .with_spacer(SPACER_SIZE)
+        .with_flex_child(
+            TextBox::new()
+                .lens(Settings::status_addr)
+                .lens(AppData::settings)
+                .expand(),
+            1.,
+        )
+        .with_tooltip("Serves the session on http://<address>/status and /events for stream overlays and bots. Keep it on 127.0.0.1 unless you trust the network.")
+}
+
+/*
+Heartbeat timing, in seconds.
+Applies to the hive when hosting and to the connection to it.
+*/
+fn heartbeat_input() -> impl Widget<AppData> {
+    Flex::row()
+        .with_child(new_label("Heartbeat every"))
+        .with_spacer(SPACER_SIZE)
+        .with_flex_child(
+            TextBox::new()
+                .with_formatter(ParseFormatter::new())
+                .lens(Settings::heartbeat_interval)
+                .lens(AppData::settings)
+                .expand(),
+            1.,
+        )
+        .with_spacer(SPACER_SIZE)
+        .with_child(new_label("s, drop after"))
+        .with_spacer(SPACER_SIZE)
+        .with_flex_child(
+            TextBox::new()
+                .with_formatter(ParseFormatter::new())
+                .lens(Settings::heartbeat_timeout)
+                .lens(AppData::settings)
+                .expand(),
+            1.,
+        )
+        .with_spacer(SPACER_SIZE)
+        .with_child(new_label("s of silence"))
+        .with_tooltip("Shorter times notice dropped connections sooner, longer ones forgive slow networks.")
 }
 
 /*
@@ -94,15 +407,104 @@ fn minecraft_browser_bottom() -> impl Widget<AppData> {
 }
 
 /*
-Generates client and server data from settings.
+Generates client configuration from settings.
 */
-fn startup_data(settings: &Settings) -> (ServerIcons, String, String, SocketAddr) {
-    (
-        ServerIcons::get_icons(),
-        settings.minecraft_path.clone() + SERVERS,
-        settings.minecraft_path.clone() + LATEST_LOG,
-        SocketAddr::from_str(&settings.server_addr).unwrap(),
-    )
+fn client_config(settings: &Settings) -> ClientConfig {
+    ClientConfig {
+        icons: ServerIcons::get_icons(),
+        server_data_path: settings.minecraft_path.clone() + SERVERS,
+        log_path: settings.minecraft_path.clone() + LATEST_LOG,
+        server_addr: settings.server_addr.clone(),
+        nickname: settings.nickname.clone(),
+        room: settings.room.trim().to_string(),
+        password: settings.password.clone(),
+        tls: settings.tls,
+        heartbeat: heartbeat_config(settings),
+        detection: settings.detection.into(),
+        sharing: settings.sharing.into(),
+        host_key: None,
+    }
+}
+
+/*
+Heartbeat timing from settings, validated beforehand.
+*/
+fn heartbeat_config(settings: &Settings) -> HeartbeatConfig {
+    HeartbeatConfig {
+        interval: Duration::from_secs_f64(settings.heartbeat_interval),
+        timeout: Duration::from_secs_f64(settings.heartbeat_timeout),
+    }
+}
+
+/*
+Name the hive is announced under.
+*/
+fn room_name(settings: &Settings) -> String {
+    match settings.nickname.is_empty() {
+        true => "HiveSearch".to_string(),
+        false => format!("{}'s hive", settings.nickname),
+    }
+}
+
+/*
+Policy of the hosted hive.
+*/
+fn host_policy(settings: &Settings) -> HostPolicy {
+    match settings.policy {
+        Policy::ShowAll => HostPolicy::ShowAll,
+        Policy::FirstOpened => HostPolicy::FirstOpened,
+        Policy::MostRecent => HostPolicy::MostRecent,
+        Policy::Priority => HostPolicy::Priority(
+            settings
+                .priority
+                .split(',')
+                .map(|nickname| nickname.trim().to_string())
+                .filter(|nickname| !nickname.is_empty())
+                .collect(),
+        ),
+    }
+}
+
+/*
+Generates server configuration from settings.
+*/
+fn server_config(settings: &Settings) -> ServerConfig {
+    ServerConfig {
+        address: SocketAddr::from_str(&settings.server_addr).unwrap(),
+        password: match settings.password.is_empty() {
+            true => None,
+            false => Some(settings.password.clone()),
+        },
+        tls: settings.tls,
+        heartbeat: heartbeat_config(settings),
+        recording: None,
+        discovery: Some(room_name(settings)),
+        relay: settings.relay,
+        policy: host_policy(settings),
+        room_expiry: Duration::from_secs_f32(server::ROOM_EXPIRY),
+        host_key: None,
+    }
+}
+
+/*
+Observer of a new session.
+Also feeds the status API, if enabled, which stops with the session.
+*/
+fn session_observer(event: &mut EventCtx, settings: &Settings, stop_token: &Arc<PauseToken>) -> Observer {
+    let ui: Observer = Arc::new(DruidObserver::new(event.get_external_handle()));
+    if !settings.status_api {
+        return ui
+    }
+    let status = Arc::new(StatusObserver::new());
+    let address = SocketAddr::from_str(&settings.status_addr).unwrap();
+    let stop_token = stop_token.clone();
+    let _status = status.clone();
+    spawn(async move {
+        if let Err(error) = status::serve(stop_token, address, _status).await {
+            warn!("[status] failed to serve on {}: {}", address, error);
+        }
+    });
+    Arc::new(FanOutObserver::new(vec![ui, status]))
 }
 
 /// Called when the 'Host' button is clicked.
@@ -110,29 +512,36 @@ fn startup_data(settings: &Settings) -> (ServerIcons, String, String, SocketAddr
 /// Validates settings and starts server and client threads.
 fn on_click_host(event: &mut EventCtx, data: &mut AppData, _env: &Env) {
     let settings = &data.settings;
-    let result = validate_settings(settings);
+    let result = validate_host_settings(settings);
     if let Ok(_) = result {
         save_settings(settings);
+        data.error.clear();
         data.state = State::Host;
         let stop_token = Arc::new(PauseToken::new(true));
         let pause_token = Arc::new(PauseToken::new(false));
         data.stop_token = Some(stop_token.clone());
         data.pause_token = Some(pause_token.clone());
-        let (icons, server_data_path, log_path, server_addr) = startup_data(settings);
+        let (command_send, command_recv) = unbounded();
+        data.commands = Some(Arc::new(command_send));
+        data.chat = Arc::new(Vec::new());
+        let (server_command_send, server_command_recv) = unbounded();
+        data.server_commands = Some(Arc::new(server_command_send));
+        let observer = session_observer(event, settings, &stop_token);
+        // Keeps the host's own client from being kicked or banned.
+        let host_key = Some(rand::random());
         let _server = server::start(
-            event.get_external_handle(),
+            observer.clone(),
             stop_token.clone(),
             pause_token.clone(),
-            server_addr.clone(),
+            ServerConfig { host_key, ..server_config(settings) },
+            server_command_recv,
         );
         let _client = client::start(
-            event.get_external_handle(),
+            observer,
             stop_token.clone(),
             pause_token.clone(),
-            icons,
-            server_data_path,
-            log_path,
-            server_addr,
+            ClientConfig { host_key, ..client_config(settings) },
+            command_recv,
         );
     }
 }
@@ -145,20 +554,21 @@ fn on_click_connect(event: &mut EventCtx, data: &mut AppData, _env: &Env) {
     let result = validate_settings(settings);
     if let Ok(_) = result {
         save_settings(settings);
+        data.error.clear();
         data.state = State::Client;
         let stop_token = Arc::new(PauseToken::new(true));
         let pause_token = Arc::new(PauseToken::new(false));
         data.stop_token = Some(stop_token.clone());
         data.pause_token = Some(pause_token.clone());
-        let (icons, server_data_path, log_path, server_addr) = startup_data(settings);
+        let (command_send, command_recv) = unbounded();
+        data.commands = Some(Arc::new(command_send));
+        data.chat = Arc::new(Vec::new());
         let _client = client::start(
-            event.get_external_handle(),
+            session_observer(event, settings, &stop_token),
             stop_token.clone(),
             pause_token.clone(),
-            icons,
-            server_data_path,
-            log_path,
-            server_addr,
+            client_config(settings),
+            command_recv,
         );
     }
 }
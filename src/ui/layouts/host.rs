@@ -1,25 +1,64 @@
+use std::sync::Arc;
+
 use async_std::task::block_on;
 use druid::widget::*;
 use druid::*;
 
+use hive_search::messages::Member;
+
 use super::super::data::*;
-use super::client::client_status;
+use super::chat::chat;
+use super::client::{client_status, searcher_to_string, timer_controls};
 use super::consts::*;
 
-use crate::ui::widgets::dyn_label::DynLabel;
-use crate::ui::widgets::wrappers::{new_button, new_label};
+use crate::ui::widgets::wrappers::new_button;
 
-pub const USER_COUNT: Selector<usize> = Selector::new("user-count");
+pub const MEMBERS: Selector<Vec<Member>> = Selector::new("members");
+pub const MEMBER_CHANGED: Selector<Member> = Selector::new("member-changed");
+pub const KICK: Selector<u64> = Selector::new("kick");
+pub const BAN: Selector<u64> = Selector::new("ban");
 
-fn host_status() -> impl Widget<AppData> {
-    Flex::column()
-        .with_flex_child(new_label("Status:").expand(), 1.)
-        .with_spacer(SPACER_SIZE)
+/*
+Connected clients with moderation buttons.
+*/
+fn roster() -> impl Widget<AppData> {
+    List::new(roster_entry)
+        .with_spacing(SPACER_SIZE)
+        .scroll()
+        .vertical()
+        .lens(AppData::roster)
+}
+
+fn roster_entry() -> impl Widget<RosterEntry> {
+    Flex::row()
         .with_flex_child(
-            DynLabel::new(user_count_to_string(&0), USER_COUNT, user_count_to_string)
+            Label::new(|entry: &RosterEntry, _env: &Env| searcher_to_string(entry))
                 .align_horizontal(UnitPoint::CENTER)
                 .background(Color::rgb8(0x90, 0x90, 0xFF))
-                .expand(),
+                .expand_width(),
+            3.,
+        )
+        .with_spacer(SPACER_SIZE)
+        .with_flex_child(
+            // The hive refuses to kick or ban its host anyway.
+            Either::new(|entry: &RosterEntry, _env| entry.host, SizedBox::empty(), moderation()),
+            2.,
+        )
+}
+
+fn moderation() -> impl Widget<RosterEntry> {
+    Flex::row()
+        .with_flex_child(
+            new_button::<RosterEntry>("Kick")
+                .on_click(|ctx, entry, _env| ctx.submit_command(KICK.with(entry.id)))
+                .expand_width(),
+            1.,
+        )
+        .with_spacer(SPACER_SIZE)
+        .with_flex_child(
+            new_button::<RosterEntry>("Ban")
+                .on_click(|ctx, entry, _env| ctx.submit_command(BAN.with(entry.id)))
+                .expand_width(),
             1.,
         )
 }
@@ -34,26 +73,22 @@ pub fn host() -> impl Widget<AppData> {
                         block_on(stop_token.resume());
                         data.stop_token = None;
                         data.pause_token = None;
+                        data.commands = None;
+                        data.server_commands = None;
+                        data.roster = Arc::new(Vec::new());
+                        data.searchers = Arc::new(Vec::new());
                     }
                 })
                 .expand(),
             1.,
         )
         .with_spacer(SPACER_SIZE)
-        .with_flex_child(
-            Flex::row()
-                .with_flex_child(host_status(), 1.)
-                .with_spacer(SPACER_SIZE)
-                .with_flex_child(client_status(), 1.),
-            1.,
-        )
+        .with_flex_child(timer_controls(), 1.)
+        .with_spacer(SPACER_SIZE)
+        .with_flex_child(client_status(), 1.)
+        .with_spacer(SPACER_SIZE)
+        .with_flex_child(roster(), 2.)
+        .with_spacer(SPACER_SIZE)
+        .with_flex_child(chat(), 3.)
         .padding(SPACER_SIZE)
 }
-
-fn user_count_to_string(count: &usize) -> String {
-    match count {
-        0 => "0 users.".to_string(),
-        1 => "1 user.".to_string(),
-        _ => count.to_string() + " users.",
-    }
-}
@@ -1,3 +1,4 @@
+pub mod chat;
 pub mod client;
 pub mod host;
 pub mod config;
@@ -6,4 +6,5 @@ pub mod main;
 pub mod data;
 pub mod delegate;
 pub mod layouts;
+pub mod observer;
 pub mod widgets;
\ No newline at end of file
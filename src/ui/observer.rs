@@ -0,0 +1,43 @@
+use druid::{ExtEventSink, Target};
+
+use hive_search::observer::{SessionEvent, SessionObserver};
+
+use crate::ui::delegate::RUNTIME_ERROR;
+use crate::ui::layouts::chat::CHAT;
+use crate::ui::layouts::client::{CONNECTION_STATUS, LAN_COUNT, LATENCY, ROSTER};
+use crate::ui::layouts::config::HIVES;
+use crate::ui::layouts::host::{MEMBERS, MEMBER_CHANGED};
+use crate::ui::widgets::timer::TIMER;
+
+/*
+Forwards session events to the UI as commands.
+*/
+pub struct DruidObserver {
+    ui_event_sink: ExtEventSink,
+}
+
+impl DruidObserver {
+    pub fn new(ui_event_sink: ExtEventSink) -> Self {
+        Self { ui_event_sink }
+    }
+}
+
+impl SessionObserver for DruidObserver {
+    fn notify(&self, event: SessionEvent) {
+        let sink = &self.ui_event_sink;
+        match event {
+            SessionEvent::Listening(_) => return,
+            SessionEvent::Members(members) => sink.submit_command(MEMBERS, members, Target::Auto),
+            SessionEvent::MemberChanged(member) => sink.submit_command(MEMBER_CHANGED, member, Target::Auto),
+            SessionEvent::Roster(members) => sink.submit_command(ROSTER, members, Target::Auto),
+            SessionEvent::LanGames(games) => sink.submit_command(LAN_COUNT, games.len(), Target::Auto),
+            SessionEvent::Latency(latency) => sink.submit_command(LATENCY, latency, Target::Auto),
+            SessionEvent::Connection(status) => sink.submit_command(CONNECTION_STATUS, status, Target::Auto),
+            SessionEvent::Timer(view) => sink.submit_command(TIMER, view, Target::Auto),
+            SessionEvent::Hives(hives) => sink.submit_command(HIVES, hives, Target::Auto),
+            SessionEvent::Chat(line) => sink.submit_command(CHAT, line, Target::Auto),
+            SessionEvent::Failed(error) => sink.submit_command(RUNTIME_ERROR, error, Target::Auto),
+        }
+        .ok();
+    }
+}
@@ -8,10 +8,11 @@ use druid::{
 
 use serde::{Deserialize, Serialize};
 
+use hive_search::timer::TimerView;
+
 use super::color_picker::RGBA;
 
-pub const TIMER_START: Selector<Instant> = Selector::new("timer-start");
-pub const TIMER_RESET: Selector<()> = Selector::new("timer-reset");
+pub const TIMER: Selector<TimerView> = Selector::new("timer");
 
 const TIMER_UPDATE_DURATION: f32 = 0.01;
 
@@ -31,6 +32,10 @@ pub struct TimerData {
     pub color: RGBA,
     pub bg_color: RGBA,
     pub min_width: f64,
+    /// Last known hive timer, shown by overlays opened mid-run.
+    #[serde(skip)]
+    #[data(same_fn = "PartialEq::eq")]
+    pub view: TimerView,
 }
 
 impl TimerData {
@@ -66,6 +71,12 @@ impl TimerData {
         })
     }
 
+    /// Whether the saved settings differ, the hive timer aside.
+    pub fn settings_differ(&self, other: &Self) -> bool {
+        let settings = |data: &Self| Self { view: TimerView::Reset, ..data.clone() };
+        settings(self) != settings(other)
+    }
+
     pub fn get_color(&self) -> Color {
         self.color.into()
     }
@@ -90,10 +101,19 @@ impl Default for TimerData {
             color: RGBA {r: 209, g: 160, b: 68, a: 220},
             bg_color: RGBA {r: 0, g: 0, b: 0, a: 0},
             min_width: 0.,
+            view: TimerView::Reset,
         }
     }
 }
 
+fn format_time(time: Duration) -> String {
+    let milliseconds = time.as_millis();
+    let minutes = milliseconds / 60000;
+    let seconds = (milliseconds / 1000) - (minutes * 60);
+    let milliseconds = milliseconds % 1000;
+    format!("{:01}:{:02}.{:02}", minutes, seconds, milliseconds / 10)
+}
+
 pub struct Timer {
     label: Label<TimerData>,
     token: TimerToken,
@@ -110,36 +130,38 @@ impl Timer {
             start: None,
         }
     }
+
+    /// Follows the hive timer.
+    /// Returns whether it runs and has to tick.
+    fn show(&mut self, view: TimerView) -> bool {
+        match view {
+            TimerView::Reset => {
+                self.start = None;
+                self.label.set_text(TIMER_DEFAULT);
+            }
+            TimerView::Running(start) => {
+                self.start = Some(start);
+            }
+            TimerView::Stopped(time) => {
+                self.start = None;
+                self.label.set_text(format_time(time));
+            }
+        }
+        self.start.is_some()
+    }
 }
 
 impl Widget<TimerData> for Timer {
     fn event(&mut self, ctx: &mut EventCtx, event: &Event, data: &mut TimerData, env: &Env) {
         self.label.event(ctx, event, data, env);
         match event {
-            Event::Command(cmd) => {
-                if let Some(start_point) = cmd.get(TIMER_START) {
-                    self.start = Some(*start_point);
-                    self.token = ctx.request_timer(Duration::from_secs_f32(TIMER_UPDATE_DURATION));
-                }
-                if let Some(_) = cmd.get(TIMER_RESET) {
-                    self.start = None;
-                    self.token = TimerToken::INVALID;
-                    self.label.set_text(TIMER_DEFAULT);
-                    ctx.request_update();
-                }
-            }
             Event::Timer(timer_token) => {
                 if self.token == *timer_token {
                     if let Some(reference) = self.start {
                         self.token =
                             ctx.request_timer(Duration::from_secs_f32(TIMER_UPDATE_DURATION));
                         let delta = Instant::now().duration_since(reference);
-                        let milliseconds = delta.as_millis();
-                        let minutes = milliseconds / 60000;
-                        let seconds = (milliseconds / 1000) - (minutes * 60);
-                        let milliseconds = milliseconds % 1000;
-                        self.label
-                            .set_text(format!("{:01}:{:02}.{:02}", minutes, seconds, milliseconds / 10));
+                        self.label.set_text(format_time(delta));
                         ctx.request_update();
                     }
                 }
@@ -183,14 +205,28 @@ impl Widget<TimerData> for Timer {
         data: &TimerData,
         env: &Env,
     ) {
+        // Opened mid-run, picks up where the hive is.
+        if let LifeCycle::WidgetAdded = event {
+            self.token = match self.show(data.view) {
+                true => ctx.request_timer(Duration::from_secs_f32(TIMER_UPDATE_DURATION)),
+                false => TimerToken::INVALID,
+            };
+        }
         self.label.lifecycle(ctx, event, data, env);
     }
 
     fn update(&mut self, ctx: &mut UpdateCtx, old_data: &TimerData, data: &TimerData, env: &Env) {
-        if !old_data.eq(data)  {
+        if old_data.settings_differ(data) {
             self.label.set_font(data.get_font(ctx.text()));
             self.label.set_text_color(data.get_color());
         }
+        if old_data.view != data.view {
+            self.token = match self.show(data.view) {
+                true => ctx.request_timer(Duration::from_secs_f32(TIMER_UPDATE_DURATION)),
+                false => TimerToken::INVALID,
+            };
+            ctx.request_layout();
+        }
         self.label.update(ctx, old_data, data, env);
     }
 
@@ -94,7 +94,7 @@ impl Widget<TimerData> for TimerConfig {
         self.inner.event(ctx, event, data, env);
         match event {
             Event::WindowConnected => {
-                *data = TimerData::load();
+                *data = TimerData { view: data.view, ..TimerData::load() };
             }
             _ => {}
         }
@@ -112,7 +112,7 @@ impl Widget<TimerData> for TimerConfig {
 
     fn update(&mut self, ctx: &mut UpdateCtx, old_data: &TimerData, data: &TimerData, env: &Env) {
         self.inner.update(ctx, old_data, data, env);
-        if !old_data.eq(data) {
+        if old_data.settings_differ(data) {
             data.save();
         }
     }
@@ -1,5 +1,3 @@
-use std::time::Instant;
-
 use druid::commands::{CLOSE_ALL_WINDOWS, CLOSE_WINDOW};
 use druid::widget::*;
 use druid::*;
@@ -7,7 +5,7 @@ use druid::*;
 use crate::ui::data::AppData;
 
 use super::my_widget_ext::MyWidgetExt;
-use super::timer::{TIMER_START, Timer, TimerData};
+use super::timer::{Timer, TimerData};
 
 pub struct TimerToggle {
     inner: Flex<TimerData>,
@@ -46,7 +44,6 @@ impl Widget<TimerData> for TimerToggle {
                 let id = window.id;
                 self.window = Some(id);
                 ctx.new_window(window);
-                ctx.submit_command(TIMER_START.with(Instant::now()).to(id));
             },
             (false, Some(window)) => {
                 ctx.submit_command(CLOSE_WINDOW.to(window));
@@ -27,6 +27,14 @@ pub fn new_button<T: Data>(
         .background(Painter::new(bg_highlight::<T>))
 }
 
+pub fn new_dynamic_button<T: Data>(
+    text: impl Fn(&T, &Env) -> String + 'static
+) -> Container<T> {
+    Label::new(text)
+        .align_horizontal(UnitPoint::CENTER)
+        .background(Painter::new(bg_highlight::<T>))
+}
+
 pub fn new_label<T: Data>(
     text: &str
 ) -> Container<T> {
@@ -0,0 +1,71 @@
+//! Hive addresses beyond IPv4 literals.
+
+mod common;
+
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+
+use async_std::task::block_on;
+
+use hive_search::client;
+use hive_search::messages::ClientMessage;
+use hive_search::server::ServerConfig;
+
+use common::*;
+
+#[test]
+fn addresses_need_a_host_and_a_port() {
+    for address in ["127.0.0.1:2137", "[::1]:2137", "[fe80::1%2]:2137", "hive.example.lan:25000", "localhost:1"] {
+        assert!(client::is_valid_address(address), "{} was refused", address);
+    }
+    for address in ["", "hive", "hive:", ":2137", "hive:99999", "::1:2137", "hive lan:2137", "127.0.0.1"] {
+        assert!(!client::is_valid_address(address), "{} was accepted", address);
+    }
+}
+
+#[test]
+fn hostnames_are_resolved() {
+    block_on(async {
+        let resolved = client::resolve("localhost:2137").await.unwrap();
+        assert!(resolved.iter().all(|address| address.ip().is_loopback() && address.port() == 2137));
+
+        let resolved = client::resolve("[::1]:2137").await.unwrap();
+        assert_eq!(resolved, vec![SocketAddr::from((Ipv6Addr::LOCALHOST, 2137))]);
+    });
+}
+
+#[test]
+fn unresolvable_addresses_are_reported() {
+    block_on(async {
+        let error = client::resolve("localhost").await.unwrap_err();
+        assert!(error.starts_with("Failed to resolve localhost"), "{}", error);
+    });
+}
+
+#[test]
+fn dual_stack_hive_takes_both_families() {
+    block_on(async {
+        let server = TestServer::with_config(ServerConfig {
+            address: "[::]:0".parse().unwrap(),
+            ..config()
+        })
+        .await;
+        let port = server.address.port();
+
+        let mut alice = ScriptedClient::join_at(SocketAddr::from((Ipv4Addr::LOCALHOST, port)), "Alice").await;
+        alice.recv().await;
+        let mut bob = ScriptedClient::join_at(SocketAddr::from((Ipv6Addr::LOCALHOST, port)), "Bob").await;
+        bob.recv().await;
+
+        // IPv4 clients are not shown as IPv4-mapped IPv6 addresses.
+        let members = server.expect_members(&["Alice", "Bob"]).await;
+        assert_eq!(members[0].ip, IpAddr::V4(Ipv4Addr::LOCALHOST));
+        assert_eq!(members[1].ip, IpAddr::V6(Ipv6Addr::LOCALHOST));
+
+        alice.send(ClientMessage::StartedHosting(25565)).await;
+        assert_eq!(hosts(bob.recv().await), vec![("Alice".to_string(), local(25565))]);
+        alice.recv().await;
+        bob.send(ClientMessage::StartedHosting(25566)).await;
+        let games = hosts(alice.recv().await);
+        assert_eq!(games[1], ("Bob".to_string(), SocketAddr::from((Ipv6Addr::LOCALHOST, 25566))));
+    });
+}
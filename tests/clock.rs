@@ -0,0 +1,63 @@
+//! Clock offset estimation.
+
+use std::time::Duration;
+
+use hive_search::timer::{ClockSync, TimerState, TimerView};
+
+#[test]
+fn offset_of_symmetric_round_trip() {
+    let mut clock = ClockSync::new();
+    // Server is 5 s ahead, 20 ms each way.
+    assert!(clock.sample(1_000_000, 6_020_000, 1_040_000));
+    assert_eq!(clock.offset(), 5_000_000);
+}
+
+#[test]
+fn shortest_round_trip_wins() {
+    let mut clock = ClockSync::new();
+    clock.sample(0, 5_010_000, 20_000);
+    // Reply got stuck on the way back, the estimate would be 150 ms off.
+    assert!(!clock.sample(100_000, 5_110_000, 420_000));
+    assert_eq!(clock.offset(), 5_000_000);
+}
+
+#[test]
+fn old_samples_are_forgotten() {
+    let mut clock = ClockSync::new();
+    clock.sample(0, 5_000_500, 1_000);
+    for i in 1..=8 {
+        clock.sample(i * 100_000, i * 100_000 + 7_010_000, i * 100_000 + 20_000);
+    }
+    assert_eq!(clock.offset(), 7_000_000);
+}
+
+#[test]
+fn clock_going_backwards_is_ignored() {
+    let mut clock = ClockSync::new();
+    assert!(!clock.sample(1_000_000, 6_000_000, 900_000));
+    assert_eq!(clock.offset(), 0);
+}
+
+#[test]
+fn timer_runs_on_the_local_clock() {
+    let mut clock = ClockSync::new();
+    clock.sample(0, 3_600_000_000, 0);
+    let started = clock.server_now() - 90_000_000;
+    match clock.view(TimerState::Running(started)) {
+        TimerView::Running(start) => {
+            let elapsed = start.elapsed();
+            assert!(elapsed >= Duration::from_secs(90) && elapsed < Duration::from_secs(91));
+        }
+        view => panic!("expected a running timer, got {:?}", view),
+    }
+}
+
+#[test]
+fn timer_stops_once() {
+    let timer = TimerState::Reset.start(1_000);
+    assert_eq!(timer.start(2_000), TimerState::Running(1_000));
+    let timer = timer.stop(4_000);
+    assert_eq!(timer, TimerState::Stopped(3_000));
+    assert_eq!(timer.stop(9_000), TimerState::Stopped(3_000));
+    assert_eq!(timer.start(10_000), TimerState::Running(10_000));
+}
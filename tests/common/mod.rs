@@ -0,0 +1,426 @@
+//! Test harness for the hive.
+//!
+//! - Starts servers on ephemeral localhost ports.
+//! - Connects scripted clients that speak the raw protocol.
+//! - Prepares `servers.dat` files in temporary directories.
+
+#![allow(dead_code)]
+
+use std::fs::File;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use async_std::channel::{unbounded, Receiver, Sender};
+use async_std::future::timeout;
+use async_std::net::TcpStream;
+use async_std::task::{sleep, spawn};
+use futures::{SinkExt, TryStreamExt};
+use serde::{Deserialize, Serialize};
+use tempfile::TempDir;
+
+use hive_search::assets::ServerIcons;
+use hive_search::auth;
+use hive_search::codec::BincodeCodec;
+use hive_search::heartbeat::HeartbeatConfig;
+use hive_search::messages::{APP_VERSION, ClientMessage, Handshake, Member, PROTOCOL_VERSION, RejectReason, ServerMessage};
+use hive_search::nbt_editor::{nbt_editor, NbtInstruction};
+use hive_search::observer::{ChannelObserver, SessionEvent};
+use hive_search::server::{self, HostPolicy, ServerCommand, ServerConfig};
+use hive_search::sync::PauseToken;
+use hive_search::transport::{self, Stream};
+
+/// Longest wait for anything to happen.
+pub const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Wait after which a message is considered not sent.
+pub const QUIET: Duration = Duration::from_millis(300);
+
+/// Socket with bincode encoding and asymetric data.
+type EncodedSocket =
+    asynchronous_codec::Framed<Stream, BincodeCodec<ClientMessage, ServerMessage>>;
+
+/// Server running in the background.
+/// Stops when dropped.
+pub struct TestServer {
+    pub address: SocketAddr,
+    pub events: Receiver<SessionEvent>,
+    pub commands: Sender<ServerCommand>,
+    stop_token: Arc<PauseToken>,
+}
+
+impl TestServer {
+    /// Starts an open room.
+    pub async fn start() -> Self {
+        Self::with_config(config()).await
+    }
+
+    /// Starts a room with a password.
+    pub async fn with_password(password: &str) -> Self {
+        Self::with_config(ServerConfig {
+            password: Some(password.to_string()),
+            ..config()
+        })
+        .await
+    }
+
+    pub async fn with_config(config: ServerConfig) -> Self {
+        let stop_token = Arc::new(PauseToken::new(true));
+        let pause_token = Arc::new(PauseToken::new(false));
+        let (observer, events) = ChannelObserver::new();
+        let (commands, commands_recv) = unbounded();
+        server::start(Arc::new(observer), stop_token.clone(), pause_token, config, commands_recv);
+        let address = loop {
+            match timeout(TIMEOUT, events.recv()).await {
+                Ok(Ok(SessionEvent::Listening(address))) => break address,
+                Ok(Ok(SessionEvent::Failed(error))) => panic!("server failed: {}", error),
+                Ok(Ok(_)) => {}
+                _ => panic!("server did not start"),
+            }
+        };
+        Self { address, events, commands, stop_token }
+    }
+
+    /// Waits for the server to report the clients.
+    pub async fn expect_members(&self, nicknames: &[&str]) -> Vec<Member> {
+        loop {
+            match timeout(TIMEOUT, self.events.recv()).await {
+                Ok(Ok(SessionEvent::Members(members)))
+                    if members.iter().map(|member| member.nickname.as_str()).eq(nicknames.iter().copied()) =>
+                {
+                    return members
+                }
+                Ok(Ok(_)) => {}
+                _ => panic!("server never reported {:?}", nicknames),
+            }
+        }
+    }
+
+    /// Waits for the server to report a change of the client.
+    pub async fn expect_member_changed(&self, nickname: &str) -> Member {
+        loop {
+            match timeout(TIMEOUT, self.events.recv()).await {
+                Ok(Ok(SessionEvent::MemberChanged(member))) if member.nickname == nickname => return member,
+                Ok(Ok(_)) => {}
+                _ => panic!("server never reported a change of {}", nickname),
+            }
+        }
+    }
+
+    /// Waits for the server to report the number of users.
+    pub async fn expect_user_count(&self, count: usize) {
+        loop {
+            match timeout(TIMEOUT, self.events.recv()).await {
+                Ok(Ok(SessionEvent::Members(members))) if members.len() == count => return,
+                Ok(Ok(_)) => {}
+                _ => panic!("server never reported {} users", count),
+            }
+        }
+    }
+}
+
+impl TestServer {
+    /// Stops the server the way the host does.
+    pub async fn stop(&self) {
+        self.stop_token.resume().await;
+    }
+}
+
+impl Drop for TestServer {
+    fn drop(&mut self) {
+        let stop_token = self.stop_token.clone();
+        spawn(async move { stop_token.resume().await });
+    }
+}
+
+/// Open room on an ephemeral localhost port.
+pub fn config() -> ServerConfig {
+    ServerConfig {
+        address: "127.0.0.1:0".parse().unwrap(),
+        password: None,
+        tls: false,
+        // Pings would only get in the way of the scripts.
+        heartbeat: HeartbeatConfig {
+            interval: Duration::from_secs(3600),
+            timeout: Duration::from_secs(7200),
+        },
+        recording: None,
+        discovery: None,
+        relay: false,
+        policy: HostPolicy::ShowAll,
+        room_expiry: Duration::from_secs(3600),
+        host_key: None,
+    }
+}
+
+/// Client that follows a script instead of the logs.
+pub struct ScriptedClient {
+    stream: EncodedSocket,
+}
+
+impl ScriptedClient {
+    /// Connects with the current protocol, fails the test if rejected.
+    pub async fn join(server: &TestServer, nickname: &str) -> Self {
+        Self::join_with_password(server, nickname, "").await
+    }
+
+    /// Connects and answers the password challenge, fails the test if rejected.
+    pub async fn join_with_password(server: &TestServer, nickname: &str, password: &str) -> Self {
+        match Self::try_join(server, handshake(nickname), password).await {
+            Ok(client) => client,
+            Err(reason) => panic!("{} was rejected: {}", nickname, reason),
+        }
+    }
+
+    /// Connects to a specific address of the server, fails the test if rejected.
+    pub async fn join_at(address: SocketAddr, nickname: &str) -> Self {
+        match Self::try_join_at(address, handshake(nickname), "", "").await {
+            Ok(client) => client,
+            Err(reason) => panic!("{} was rejected: {}", nickname, reason),
+        }
+    }
+
+    /// Connects with the given handshake to the default room.
+    /// Returns the rejection reason if the server refused.
+    pub async fn try_join(
+        server: &TestServer,
+        handshake: Handshake,
+        password: &str,
+    ) -> Result<Self, RejectReason> {
+        Self::try_join_room(server, handshake, "", password).await
+    }
+
+    /// Connects with the given handshake to the given room.
+    /// Returns the rejection reason if the server refused.
+    pub async fn try_join_room(
+        server: &TestServer,
+        handshake: Handshake,
+        room: &str,
+        password: &str,
+    ) -> Result<Self, RejectReason> {
+        Self::try_join_at(server.address, handshake, room, password).await
+    }
+
+    /// Opens the connection without a handshake.
+    pub async fn connect(server: &TestServer) -> Self {
+        Self::connect_at(server.address).await
+    }
+
+    async fn connect_at(address: SocketAddr) -> Self {
+        let stream = TcpStream::connect(address).await.unwrap();
+        Self {
+            stream: asynchronous_codec::Framed::new(transport::plain(stream), BincodeCodec::new()),
+        }
+    }
+
+    async fn try_join_at(
+        address: SocketAddr,
+        handshake: Handshake,
+        room: &str,
+        password: &str,
+    ) -> Result<Self, RejectReason> {
+        let mut client = Self::connect_at(address).await;
+        client.send(ClientMessage::Handshake(handshake)).await;
+        client.send(ClientMessage::JoinRoom(room.to_string())).await;
+        loop {
+            match client.recv().await {
+                ServerMessage::Accepted => return Ok(client),
+                ServerMessage::Rejected(reason) => return Err(reason),
+                ServerMessage::Challenge(challenge) => {
+                    client
+                        .send(ClientMessage::ChallengeResponse(auth::prove(password, &challenge)))
+                        .await
+                }
+                message => panic!("unexpected message during the handshake: {:?}", message),
+            }
+        }
+    }
+
+    pub async fn send(&mut self, message: ClientMessage) {
+        self.stream.send(message).await.expect("failed to send");
+    }
+
+    /// Receives the next message, skips heartbeats and roster updates.
+    pub async fn recv(&mut self) -> ServerMessage {
+        self.try_recv(TIMEOUT).await.expect("no message from the server")
+    }
+
+    /// Receives the next message if one arrives in time, skips heartbeats and roster updates.
+    pub async fn try_recv(&mut self, wait: Duration) -> Option<ServerMessage> {
+        self.next(wait, |message| !is_roster(message)).await
+    }
+
+    /// Receives the next roster update, skips everything else.
+    pub async fn recv_roster(&mut self) -> ServerMessage {
+        self.next(TIMEOUT, is_roster).await.expect("no roster update from the server")
+    }
+
+    /// Receives the next wanted message if one arrives in time, skips heartbeats.
+    async fn next(&mut self, wait: Duration, wanted: impl Fn(&ServerMessage) -> bool) -> Option<ServerMessage> {
+        loop {
+            match timeout(wait, self.stream.try_next()).await {
+                Ok(Ok(Some(ServerMessage::Ping(_)))) | Ok(Ok(Some(ServerMessage::Pong(_)))) => {}
+                Ok(Ok(Some(message))) if wanted(&message) => return Some(message),
+                Ok(Ok(Some(_))) => {}
+                Ok(_) => panic!("server closed the connection"),
+                Err(_) => return None,
+            }
+        }
+    }
+
+    /// Fails the test unless the server closes the connection.
+    pub async fn expect_closed(&mut self) {
+        match timeout(TIMEOUT, self.stream.try_next()).await {
+            Ok(Ok(None)) | Ok(Err(_)) => {}
+            Ok(Ok(Some(message))) => panic!("unexpected message: {:?}", message),
+            Err(_) => panic!("server did not close the connection"),
+        }
+    }
+
+    /// Fails the test if the server sends anything soon.
+    pub async fn expect_silence(&mut self) {
+        if let Some(message) = self.try_recv(QUIET).await {
+            panic!("unexpected message: {:?}", message);
+        }
+    }
+
+    /// Receives the next message and turns it into an NBT instruction,
+    /// the way the real client does.
+    pub async fn recv_instruction(&mut self) -> NbtInstruction {
+        match self.recv().await {
+            ServerMessage::NoHost => NbtInstruction::SetToNoHost,
+            ServerMessage::OneHost(game) => NbtInstruction::SetToOneHost(game),
+            ServerMessage::ManyHosts(games) => NbtInstruction::SetToManyHosts(games),
+            ServerMessage::ServerShutdown(_) => NbtInstruction::SetToOffline,
+            message => panic!("expected a state update, got {:?}", message),
+        }
+    }
+}
+
+/// Handshake of the current version.
+pub fn handshake(nickname: &str) -> Handshake {
+    Handshake {
+        protocol_version: PROTOCOL_VERSION,
+        app_version: APP_VERSION.to_string(),
+        nickname: nickname.to_string(),
+    }
+}
+
+/// Minecraft server entry, as stored in `servers.dat`.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Server {
+    pub name: Option<String>,
+    pub ip: Option<String>,
+    pub icon: Option<String>,
+}
+
+impl Server {
+    pub fn new(name: &str, ip: &str) -> Self {
+        Self {
+            name: Some(name.to_string()),
+            ip: Some(ip.to_string()),
+            icon: None,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct ServerData {
+    servers: Vec<Server>,
+}
+
+/// Minecraft folder in a temporary directory.
+/// Removed when dropped.
+pub struct TestMinecraft {
+    dir: TempDir,
+}
+
+impl TestMinecraft {
+    /// Creates `servers.dat` with the given entries.
+    pub fn new(servers: Vec<Server>) -> Self {
+        let minecraft = Self {
+            dir: tempfile::tempdir().unwrap(),
+        };
+        minecraft.write(servers);
+        minecraft
+    }
+
+    pub fn servers_path(&self) -> PathBuf {
+        self.dir.path().join("servers.dat")
+    }
+
+    pub fn write(&self, servers: Vec<Server>) {
+        let mut file = File::create(self.servers_path()).unwrap();
+        nbt::to_writer(&mut file, &ServerData { servers }, None).unwrap();
+    }
+
+    /// Returns `None` if the file is being written to.
+    pub fn read(&self) -> Option<Vec<Server>> {
+        let file = File::open(self.servers_path()).ok()?;
+        let data: ServerData = nbt::from_reader(file).ok()?;
+        Some(data.servers)
+    }
+
+    /// Runs the NBT editor on `servers.dat`.
+    /// Returns the instruction feed, the editor stops when it is dropped.
+    pub fn nbt_editor(&self) -> Sender<NbtInstruction> {
+        let (instruction_send, instruction_recv) = unbounded();
+        let icons = ServerIcons {
+            no_hosts: None,
+            many_hosts: None,
+        };
+        spawn(nbt_editor(
+            Arc::new(PauseToken::new(true)),
+            Arc::new(PauseToken::new(false)),
+            instruction_recv,
+            icons,
+            self.servers_path().to_str().unwrap().to_string(),
+        ));
+        instruction_send
+    }
+
+    /// Waits until `servers.dat` satisfies the condition.
+    pub async fn expect(&self, condition: impl Fn(&[Server]) -> bool) -> Vec<Server> {
+        let start = Instant::now();
+        loop {
+            let servers = self.read();
+            if let Some(servers) = &servers {
+                if condition(servers) {
+                    return servers.clone()
+                }
+            }
+            if start.elapsed() > TIMEOUT {
+                panic!("unexpected servers.dat contents: {:?}", servers);
+            }
+            sleep(Duration::from_millis(20)).await;
+        }
+    }
+}
+
+/// Whether the message updates the roster.
+pub fn is_roster(message: &ServerMessage) -> bool {
+    matches!(
+        message,
+        ServerMessage::Roster(_) | ServerMessage::MemberChanged(_) | ServerMessage::MemberLeft(_)
+    )
+}
+
+/// Nicknames and addresses of the games in a state update.
+pub fn hosts(message: ServerMessage) -> Vec<(String, SocketAddr)> {
+    let games = match message {
+        ServerMessage::NoHost => vec![],
+        ServerMessage::OneHost(game) => vec![game],
+        ServerMessage::ManyHosts(games) => games,
+        message => panic!("expected a state update, got {:?}", message),
+    };
+    games
+        .into_iter()
+        .map(|game| (game.nickname, game.address))
+        .collect()
+}
+
+/// Local game address.
+pub fn local(port: u16) -> SocketAddr {
+    SocketAddr::from(([127, 0, 0, 1], port))
+}
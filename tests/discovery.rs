@@ -0,0 +1,101 @@
+//! Hive beacons on the local network.
+
+use std::net::{Ipv4Addr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_std::future::timeout;
+use async_std::net::UdpSocket;
+use async_std::channel::Receiver;
+use async_std::task::{block_on, spawn};
+
+use hive_search::discovery::{self, Beacon, Hive};
+use hive_search::messages::PROTOCOL_VERSION;
+use hive_search::observer::{ChannelObserver, SessionEvent};
+use hive_search::sync::PauseToken;
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Starts a listener on a loopback port.
+async fn listener(stop: &Arc<PauseToken>) -> (SocketAddr, Receiver<SessionEvent>) {
+    let socket = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+    let address = socket.local_addr().unwrap();
+    let (observer, events) = ChannelObserver::new();
+    spawn(discovery::listen(Arc::new(observer), stop.clone(), socket));
+    (address, events)
+}
+
+fn beacon(name: &str, port: u16) -> Beacon {
+    Beacon { protocol_version: PROTOCOL_VERSION, name: name.to_string(), port }
+}
+
+async fn expect_hives(events: &Receiver<SessionEvent>) -> Vec<Hive> {
+    loop {
+        match timeout(TIMEOUT, events.recv()).await {
+            Ok(Ok(SessionEvent::Hives(hives))) => return hives,
+            Ok(Ok(_)) => continue,
+            _ => panic!("no hives reported"),
+        }
+    }
+}
+
+#[test]
+fn announced_hive_is_listed() {
+    block_on(async {
+        let stop = Arc::new(PauseToken::new(true));
+        let (address, events) = listener(&stop).await;
+        spawn(discovery::announce(stop.clone(), beacon("Bees", 4321), address));
+
+        let hives = expect_hives(&events).await;
+        assert_eq!(hives, vec![Hive { name: "Bees".to_string(), address: SocketAddr::from((Ipv4Addr::LOCALHOST, 4321)) }]);
+        stop.resume().await;
+    });
+}
+
+#[test]
+fn hives_are_sorted_by_name() {
+    block_on(async {
+        let stop = Arc::new(PauseToken::new(true));
+        let (address, events) = listener(&stop).await;
+        spawn(discovery::announce(stop.clone(), beacon("Wasps", 1000), address));
+        spawn(discovery::announce(stop.clone(), beacon("Ants", 2000), address));
+
+        let mut hives = expect_hives(&events).await;
+        while hives.len() < 2 {
+            hives = expect_hives(&events).await;
+        }
+        let names: Vec<&str> = hives.iter().map(|hive| hive.name.as_str()).collect();
+        assert_eq!(names, ["Ants", "Wasps"]);
+        stop.resume().await;
+    });
+}
+
+#[test]
+fn other_protocol_versions_are_ignored() {
+    block_on(async {
+        let stop = Arc::new(PauseToken::new(true));
+        let (address, events) = listener(&stop).await;
+        let mut old = beacon("Old", 1000);
+        old.protocol_version = PROTOCOL_VERSION - 1;
+        spawn(discovery::announce(stop.clone(), old, address));
+        spawn(discovery::announce(stop.clone(), beacon("New", 2000), address));
+
+        let hives = expect_hives(&events).await;
+        assert_eq!(hives.len(), 1);
+        assert_eq!(hives[0].name, "New");
+        stop.resume().await;
+    });
+}
+
+#[test]
+fn stray_packets_are_ignored() {
+    block_on(async {
+        let stop = Arc::new(PauseToken::new(true));
+        let (address, events) = listener(&stop).await;
+        let socket = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+        socket.send_to(b"not a beacon", address).await.unwrap();
+
+        assert!(timeout(Duration::from_millis(500), events.recv()).await.is_err());
+        stop.resume().await;
+    });
+}
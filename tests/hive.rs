@@ -0,0 +1,574 @@
+//! End-to-end tests of the hive.
+
+mod common;
+
+use std::net::IpAddr;
+use std::time::SystemTime;
+
+use async_std::task::block_on;
+
+use hive_search::messages::{ChatContent, ClientMessage, Handshake, MAX_CHAT_LENGTH, QuickPing, RejectReason, ServerMessage, ShutdownReason};
+use hive_search::recording::{Record, StateEvent};
+use hive_search::server::{self, HostPolicy, ServerCommand, ServerConfig};
+use hive_search::timer::{self, TimerState};
+
+use common::*;
+
+#[test]
+fn joining_client_gets_current_state() {
+    block_on(async {
+        let server = TestServer::start().await;
+        let mut alice = ScriptedClient::join(&server, "Alice").await;
+        assert!(matches!(alice.recv().await, ServerMessage::NoHost));
+
+        alice.send(ClientMessage::StartedHosting(25565)).await;
+        assert_eq!(hosts(alice.recv().await), vec![("Alice".to_string(), local(25565))]);
+
+        let mut bob = ScriptedClient::join(&server, "Bob").await;
+        assert!(matches!(bob.recv().await, ServerMessage::OneHost(_)));
+    });
+}
+
+#[test]
+fn hosting_is_broadcast_to_everyone() {
+    block_on(async {
+        let server = TestServer::start().await;
+        let mut alice = ScriptedClient::join(&server, "Alice").await;
+        alice.recv().await;
+        let mut bob = ScriptedClient::join(&server, "Bob").await;
+        bob.recv().await;
+
+        alice.send(ClientMessage::StartedHosting(25565)).await;
+        let expected = vec![("Alice".to_string(), local(25565))];
+        assert_eq!(hosts(alice.recv().await), expected);
+        assert_eq!(hosts(bob.recv().await), expected);
+    });
+}
+
+#[test]
+fn games_are_listed_oldest_first() {
+    block_on(async {
+        let server = TestServer::start().await;
+        let mut alice = ScriptedClient::join(&server, "Alice").await;
+        alice.recv().await;
+        let mut bob = ScriptedClient::join(&server, "Bob").await;
+        bob.recv().await;
+        let mut carol = ScriptedClient::join(&server, "Carol").await;
+        carol.recv().await;
+
+        bob.send(ClientMessage::StartedHosting(1111)).await;
+        assert_eq!(hosts(carol.recv().await).len(), 1);
+        alice.send(ClientMessage::StartedHosting(2222)).await;
+        let update = carol.recv().await;
+        assert!(matches!(update, ServerMessage::ManyHosts(_)));
+        assert_eq!(
+            hosts(update),
+            vec![("Bob".to_string(), local(1111)), ("Alice".to_string(), local(2222))]
+        );
+
+        bob.send(ClientMessage::StoppedHosting).await;
+        let update = carol.recv().await;
+        assert!(matches!(update, ServerMessage::OneHost(_)));
+        assert_eq!(hosts(update), vec![("Alice".to_string(), local(2222))]);
+
+        alice.send(ClientMessage::StoppedHosting).await;
+        assert!(matches!(carol.recv().await, ServerMessage::NoHost));
+    });
+}
+
+#[test]
+fn repeated_announcement_is_not_broadcast() {
+    block_on(async {
+        let server = TestServer::start().await;
+        let mut alice = ScriptedClient::join(&server, "Alice").await;
+        alice.recv().await;
+        let mut bob = ScriptedClient::join(&server, "Bob").await;
+        bob.recv().await;
+
+        alice.send(ClientMessage::StartedHosting(25565)).await;
+        bob.recv().await;
+        alice.send(ClientMessage::StartedHosting(25565)).await;
+        bob.expect_silence().await;
+
+        alice.send(ClientMessage::StartedHosting(25566)).await;
+        assert_eq!(hosts(bob.recv().await), vec![("Alice".to_string(), local(25566))]);
+    });
+}
+
+#[test]
+fn stopping_without_hosting_is_not_broadcast() {
+    block_on(async {
+        let server = TestServer::start().await;
+        let mut alice = ScriptedClient::join(&server, "Alice").await;
+        alice.recv().await;
+        let mut bob = ScriptedClient::join(&server, "Bob").await;
+        bob.recv().await;
+
+        alice.send(ClientMessage::StoppedHosting).await;
+        bob.expect_silence().await;
+    });
+}
+
+#[test]
+fn disconnect_closes_the_game() {
+    block_on(async {
+        let server = TestServer::start().await;
+        let mut alice = ScriptedClient::join(&server, "Alice").await;
+        alice.recv().await;
+        let mut bob = ScriptedClient::join(&server, "Bob").await;
+        bob.recv().await;
+        server.expect_user_count(2).await;
+
+        alice.send(ClientMessage::StartedHosting(25565)).await;
+        bob.recv().await;
+        drop(alice);
+        assert!(matches!(bob.recv().await, ServerMessage::NoHost));
+        server.expect_user_count(1).await;
+    });
+}
+
+#[test]
+fn empty_nickname_falls_back_to_ip() {
+    block_on(async {
+        let server = TestServer::start().await;
+        let mut alice = ScriptedClient::join(&server, "").await;
+        alice.recv().await;
+
+        alice.send(ClientMessage::StartedHosting(25565)).await;
+        assert_eq!(hosts(alice.recv().await), vec![("127.0.0.1".to_string(), local(25565))]);
+    });
+}
+
+#[test]
+fn protocol_mismatch_is_rejected() {
+    block_on(async {
+        let server = TestServer::start().await;
+        let handshake = Handshake {
+            protocol_version: 0,
+            ..handshake("Alice")
+        };
+        let result = ScriptedClient::try_join(&server, handshake, "").await;
+        assert!(matches!(result, Err(RejectReason::ProtocolMismatch(_, _))));
+    });
+}
+
+#[test]
+fn invalid_nickname_is_rejected() {
+    block_on(async {
+        let server = TestServer::start().await;
+        let result = ScriptedClient::try_join(&server, handshake("§cAlice"), "").await;
+        assert!(matches!(result, Err(RejectReason::InvalidNickname)));
+        let result = ScriptedClient::try_join(&server, handshake(&"A".repeat(33)), "").await;
+        assert!(matches!(result, Err(RejectReason::InvalidNickname)));
+    });
+}
+
+#[test]
+fn password_is_required() {
+    block_on(async {
+        let server = TestServer::with_password("hunter2").await;
+        let result = ScriptedClient::try_join(&server, handshake("Mallory"), "hunter3").await;
+        assert!(matches!(result, Err(RejectReason::WrongPassword)));
+
+        let mut alice = ScriptedClient::join_with_password(&server, "Alice", "hunter2").await;
+        assert!(matches!(alice.recv().await, ServerMessage::NoHost));
+    });
+}
+
+#[test]
+fn servers_dat_follows_the_hive() {
+    block_on(async {
+        let server = TestServer::start().await;
+        let minecraft = TestMinecraft::new(vec![
+            Server::new("My Server", "example.com"),
+            Server::new("Friend's Server", "example.org"),
+        ]);
+        let nbt_editor = minecraft.nbt_editor();
+        let mut alice = ScriptedClient::join(&server, "Alice").await;
+        alice.recv().await;
+        let mut bob = ScriptedClient::join(&server, "Bob").await;
+        nbt_editor.send(bob.recv_instruction().await).await.unwrap();
+
+        // Marked entries are appended if there were none.
+        let servers = minecraft.expect(|servers| servers.len() == 3).await;
+        assert_eq!(servers[0], Server::new("My Server", "example.com"));
+        assert_eq!(servers[1], Server::new("Friend's Server", "example.org"));
+        assert!(servers[2].name.as_ref().unwrap().ends_with("No Games Open"));
+        assert_eq!(servers[2].ip, None);
+
+        alice.send(ClientMessage::StartedHosting(25565)).await;
+        nbt_editor.send(bob.recv_instruction().await).await.unwrap();
+        let servers = minecraft
+            .expect(|servers| servers[2].ip.is_some())
+            .await;
+        assert_eq!(servers.len(), 3);
+        assert!(servers[2].name.as_ref().unwrap().ends_with("Alice's Game"));
+        assert_eq!(servers[2].ip.as_deref(), Some("127.0.0.1:25565"));
+
+        bob.send(ClientMessage::StartedHosting(25566)).await;
+        nbt_editor.send(bob.recv_instruction().await).await.unwrap();
+        let servers = minecraft.expect(|servers| servers.len() == 4).await;
+        assert!(servers[2].name.as_ref().unwrap().ends_with("Alice's Game"));
+        assert!(servers[3].name.as_ref().unwrap().ends_with("Bob's Game"));
+
+        // The player moves the marked entries to the top and adds a server.
+        let mut reordered = servers[2..].to_vec();
+        reordered.extend_from_slice(&servers[..2]);
+        reordered.push(Server::new("New Server", "example.net"));
+        minecraft.write(reordered);
+
+        alice.send(ClientMessage::StoppedHosting).await;
+        nbt_editor.send(bob.recv_instruction().await).await.unwrap();
+        let servers = minecraft.expect(|servers| servers.len() == 4).await;
+        assert!(servers[0].name.as_ref().unwrap().ends_with("Bob's Game"));
+        assert_eq!(servers[1], Server::new("My Server", "example.com"));
+        assert_eq!(servers[2], Server::new("Friend's Server", "example.org"));
+        assert_eq!(servers[3], Server::new("New Server", "example.net"));
+    });
+}
+
+#[test]
+fn stopping_tells_everyone() {
+    block_on(async {
+        let server = TestServer::start().await;
+        let mut alice = ScriptedClient::join(&server, "Alice").await;
+        alice.recv().await;
+        let mut bob = ScriptedClient::join(&server, "Bob").await;
+        bob.recv().await;
+        alice.send(ClientMessage::StartedHosting(25565)).await;
+        alice.recv().await;
+        bob.recv().await;
+
+        server.stop().await;
+        for client in [&mut alice, &mut bob] {
+            assert!(matches!(client.recv().await, ServerMessage::ServerShutdown(ShutdownReason::Stopped)));
+            client.expect_closed().await;
+        }
+    });
+}
+
+#[test]
+fn servers_dat_shows_the_hive_offline() {
+    block_on(async {
+        let server = TestServer::start().await;
+        let minecraft = TestMinecraft::new(vec![Server::new("My Server", "example.com")]);
+        let nbt_editor = minecraft.nbt_editor();
+        let mut alice = ScriptedClient::join(&server, "Alice").await;
+        alice.recv().await;
+        let mut bob = ScriptedClient::join(&server, "Bob").await;
+        bob.recv().await;
+        alice.send(ClientMessage::StartedHosting(25565)).await;
+        nbt_editor.send(bob.recv_instruction().await).await.unwrap();
+        minecraft.expect(|servers| servers.len() == 2).await;
+
+        server.stop().await;
+        nbt_editor.send(bob.recv_instruction().await).await.unwrap();
+        let servers = minecraft.expect(|servers| servers[1].ip.is_none()).await;
+        assert_eq!(servers.len(), 2);
+        assert!(servers[1].name.as_ref().unwrap().ends_with("Hive Offline"));
+    });
+}
+
+#[test]
+fn clock_requests_are_answered() {
+    block_on(async {
+        let server = TestServer::start().await;
+        let mut alice = ScriptedClient::join(&server, "Alice").await;
+        alice.recv().await;
+
+        let sent = timer::now();
+        alice.send(ClientMessage::TimeRequest(sent)).await;
+        match alice.recv().await {
+            ServerMessage::TimeReply(echoed, server_time) => {
+                assert_eq!(echoed, sent);
+                assert!(server_time >= sent && server_time <= timer::now());
+            }
+            message => panic!("expected a time reply, got {:?}", message),
+        }
+    });
+}
+
+#[test]
+fn timer_is_shared_by_the_hive() {
+    block_on(async {
+        let server = TestServer::start().await;
+        let mut alice = ScriptedClient::join(&server, "Alice").await;
+        alice.recv().await;
+        let mut bob = ScriptedClient::join(&server, "Bob").await;
+        bob.recv().await;
+
+        alice.send(ClientMessage::TimerStart(1_000_000)).await;
+        assert!(matches!(alice.recv().await, ServerMessage::Timer(TimerState::Running(1_000_000))));
+        assert!(matches!(bob.recv().await, ServerMessage::Timer(TimerState::Running(1_000_000))));
+
+        // Pressing start twice does not restart the run.
+        bob.send(ClientMessage::TimerStart(2_000_000)).await;
+        alice.expect_silence().await;
+
+        // Late joiners get the running timer after the games.
+        let mut carol = ScriptedClient::join(&server, "Carol").await;
+        assert!(matches!(carol.recv().await, ServerMessage::NoHost));
+        assert!(matches!(carol.recv().await, ServerMessage::Timer(TimerState::Running(1_000_000))));
+
+        bob.send(ClientMessage::TimerStop(3_500_000)).await;
+        assert!(matches!(alice.recv().await, ServerMessage::Timer(TimerState::Stopped(2_500_000))));
+        assert!(matches!(carol.recv().await, ServerMessage::Timer(TimerState::Stopped(2_500_000))));
+
+        carol.send(ClientMessage::TimerReset).await;
+        assert!(matches!(alice.recv().await, ServerMessage::Timer(TimerState::Reset)));
+    });
+}
+
+#[test]
+fn kicked_client_is_told_and_dropped() {
+    block_on(async {
+        let server = TestServer::start().await;
+        let mut alice = ScriptedClient::join(&server, "Alice").await;
+        alice.recv().await;
+        let mut bob = ScriptedClient::join(&server, "Bob").await;
+        bob.recv().await;
+        let members = server.expect_members(&["Alice", "Bob"]).await;
+
+        bob.send(ClientMessage::StartedHosting(25565)).await;
+        alice.recv().await;
+        bob.recv().await;
+        server.commands.send(ServerCommand::Kick(members[1].id)).await.unwrap();
+        assert!(matches!(bob.recv().await, ServerMessage::Kicked(false)));
+        bob.expect_closed().await;
+        assert!(matches!(alice.recv().await, ServerMessage::NoHost));
+        server.expect_members(&["Alice"]).await;
+
+        // Kicked clients may come back.
+        ScriptedClient::join(&server, "Bob").await;
+    });
+}
+
+/// Clients sent `Kicked(true)` when the events are replayed, by id.
+async fn banned(events: Vec<StateEvent>) -> Vec<u64> {
+    let records = events.into_iter().map(|event| Record { at: SystemTime::now(), event }).collect();
+    let mut banned: Vec<u64> = server::replay(records)
+        .await
+        .into_iter()
+        .flat_map(|replayed| replayed.sent)
+        .filter(|(_, message)| matches!(message, ServerMessage::Kicked(true)))
+        .map(|(id, _)| id)
+        .collect();
+    banned.sort();
+    banned
+}
+
+fn joined(id: u64, ip: &str) -> StateEvent {
+    StateEvent::Joined(id, format!("Searcher {}", id), ip.parse::<IpAddr>().unwrap(), String::new())
+}
+
+#[test]
+fn ban_kicks_everyone_from_the_ip() {
+    block_on(async {
+        let kicked = banned(vec![
+            joined(1, "203.0.113.7"),
+            joined(2, "203.0.113.7"),
+            joined(3, "198.51.100.2"),
+            StateEvent::Command(ServerCommand::Ban(1)),
+        ])
+        .await;
+        assert_eq!(kicked, vec![1, 2]);
+    });
+}
+
+#[test]
+fn host_ip_is_never_banned() {
+    block_on(async {
+        // The host shares the IP with a searcher, behind the same NAT.
+        let kicked = banned(vec![
+            joined(1, "203.0.113.7"),
+            StateEvent::Message(1, ClientMessage::HostKey(7)),
+            joined(2, "203.0.113.7"),
+            StateEvent::Command(ServerCommand::Ban(2)),
+            StateEvent::Command(ServerCommand::Ban(1)),
+        ])
+        .await;
+        assert!(kicked.is_empty());
+
+        let server = TestServer::with_config(ServerConfig {
+            host_key: Some(7),
+            ..config()
+        })
+        .await;
+        let mut alice = ScriptedClient::join(&server, "Alice").await;
+        alice.recv().await;
+        alice.send(ClientMessage::HostKey(7)).await;
+        let alice_id = server.expect_member_changed("Alice").await.id;
+        let mut bob = ScriptedClient::join(&server, "Bob").await;
+        bob.recv().await;
+        let members = server.expect_members(&["Alice", "Bob"]).await;
+        server.commands.send(ServerCommand::Ban(alice_id)).await.unwrap();
+        server.commands.send(ServerCommand::Ban(members[1].id)).await.unwrap();
+        alice.expect_silence().await;
+        bob.expect_silence().await;
+        ScriptedClient::join(&server, "Carol").await;
+    });
+}
+
+#[test]
+fn banned_ip_is_turned_away() {
+    block_on(async {
+        // Nobody proves to be the host, so localhost can be banned.
+        let server = TestServer::start().await;
+        let mut alice = ScriptedClient::join(&server, "Alice").await;
+        alice.recv().await;
+        let mut bob = ScriptedClient::join(&server, "Bob").await;
+        bob.recv().await;
+        let members = server.expect_members(&["Alice", "Bob"]).await;
+        server.commands.send(ServerCommand::Ban(members[1].id)).await.unwrap();
+
+        // Both connect from localhost, so both go.
+        assert!(matches!(alice.recv().await, ServerMessage::Kicked(true)));
+        assert!(matches!(bob.recv().await, ServerMessage::Kicked(true)));
+        server.expect_members(&[]).await;
+
+        let mut bob = ScriptedClient::connect(&server).await;
+        assert!(matches!(bob.recv().await, ServerMessage::Rejected(RejectReason::Banned)));
+        bob.expect_closed().await;
+    });
+}
+
+#[test]
+fn host_cannot_be_kicked() {
+    block_on(async {
+        let server = TestServer::with_config(ServerConfig {
+            host_key: Some(7),
+            ..config()
+        })
+        .await;
+        let mut alice = ScriptedClient::join(&server, "Alice").await;
+        alice.recv().await;
+        alice.send(ClientMessage::HostKey(7)).await;
+        assert!(server.expect_member_changed("Alice").await.host);
+        let mut mallory = ScriptedClient::join(&server, "Mallory").await;
+        mallory.recv().await;
+        mallory.send(ClientMessage::HostKey(8)).await;
+        let members = server.expect_members(&["Alice", "Mallory"]).await;
+        assert!(members[0].host);
+
+        server.commands.send(ServerCommand::Kick(members[0].id)).await.unwrap();
+        server.commands.send(ServerCommand::Kick(members[1].id)).await.unwrap();
+        assert!(matches!(mallory.recv().await, ServerMessage::Kicked(false)));
+        alice.expect_silence().await;
+    });
+}
+
+#[test]
+fn chat_reaches_everyone_with_the_nickname() {
+    block_on(async {
+        let server = TestServer::start().await;
+        let mut alice = ScriptedClient::join(&server, "Alice").await;
+        alice.recv().await;
+        let mut bob = ScriptedClient::join(&server, "Bob").await;
+        bob.recv().await;
+
+        alice.send(ClientMessage::Chat(ChatContent::Text("  blind at 3000 1200 ".to_string()))).await;
+        for client in [&mut alice, &mut bob] {
+            match client.recv().await {
+                ServerMessage::Chat(line) => {
+                    assert_eq!(line.nickname, "Alice");
+                    assert_eq!(line.content, ChatContent::Text("blind at 3000 1200".to_string()));
+                }
+                message => panic!("expected chat, got {:?}", message),
+            }
+        }
+
+        bob.send(ClientMessage::Chat(ChatContent::Ping(QuickPing::Resetting))).await;
+        assert!(matches!(alice.recv().await, ServerMessage::Chat(line) if line.content == ChatContent::Ping(QuickPing::Resetting)));
+    });
+}
+
+#[test]
+fn empty_and_overlong_chat_is_dropped() {
+    block_on(async {
+        let server = TestServer::start().await;
+        let mut alice = ScriptedClient::join(&server, "Alice").await;
+        alice.recv().await;
+
+        alice.send(ClientMessage::Chat(ChatContent::Text("   ".to_string()))).await;
+        alice.send(ClientMessage::Chat(ChatContent::Text("a".repeat(MAX_CHAT_LENGTH + 1)))).await;
+        alice.expect_silence().await;
+
+        alice.send(ClientMessage::Chat(ChatContent::Text("a".repeat(MAX_CHAT_LENGTH)))).await;
+        assert!(matches!(alice.recv().await, ServerMessage::Chat(_)));
+    });
+}
+
+/// Hive showing one game at a time, with Alice, Bob and Carol connected.
+async fn one_game_hive(policy: HostPolicy) -> (TestServer, ScriptedClient, ScriptedClient, ScriptedClient) {
+    let server = TestServer::with_config(ServerConfig { policy, ..config() }).await;
+    let mut alice = ScriptedClient::join(&server, "Alice").await;
+    alice.recv().await;
+    let mut bob = ScriptedClient::join(&server, "Bob").await;
+    bob.recv().await;
+    let mut carol = ScriptedClient::join(&server, "Carol").await;
+    carol.recv().await;
+    (server, alice, bob, carol)
+}
+
+#[test]
+fn first_opened_game_is_shown_until_it_closes() {
+    block_on(async {
+        let (_server, mut alice, mut bob, mut carol) = one_game_hive(HostPolicy::FirstOpened).await;
+
+        alice.send(ClientMessage::StartedHosting(1111)).await;
+        assert_eq!(hosts(carol.recv().await), vec![("Alice".to_string(), local(1111))]);
+        bob.send(ClientMessage::StartedHosting(2222)).await;
+        carol.expect_silence().await;
+
+        alice.send(ClientMessage::StoppedHosting).await;
+        let update = carol.recv().await;
+        assert!(matches!(update, ServerMessage::OneHost(_)));
+        assert_eq!(hosts(update), vec![("Bob".to_string(), local(2222))]);
+
+        bob.send(ClientMessage::StoppedHosting).await;
+        assert!(matches!(carol.recv().await, ServerMessage::NoHost));
+    });
+}
+
+#[test]
+fn most_recent_game_is_shown_until_it_closes() {
+    block_on(async {
+        let (_server, mut alice, mut bob, mut carol) = one_game_hive(HostPolicy::MostRecent).await;
+
+        alice.send(ClientMessage::StartedHosting(1111)).await;
+        assert_eq!(hosts(carol.recv().await), vec![("Alice".to_string(), local(1111))]);
+        bob.send(ClientMessage::StartedHosting(2222)).await;
+        assert_eq!(hosts(carol.recv().await), vec![("Bob".to_string(), local(2222))]);
+
+        // The queued game closing changes nothing.
+        alice.send(ClientMessage::StoppedHosting).await;
+        carol.expect_silence().await;
+        alice.send(ClientMessage::StartedHosting(1111)).await;
+        assert_eq!(hosts(carol.recv().await), vec![("Alice".to_string(), local(1111))]);
+
+        alice.send(ClientMessage::StoppedHosting).await;
+        assert_eq!(hosts(carol.recv().await), vec![("Bob".to_string(), local(2222))]);
+    });
+}
+
+#[test]
+fn priority_prefers_earlier_nicknames() {
+    block_on(async {
+        let priority = HostPolicy::Priority(vec!["Bob".to_string(), "Alice".to_string()]);
+        let (_server, mut alice, mut bob, mut carol) = one_game_hive(priority).await;
+
+        // Unlisted searchers are still shown when nobody else hosts.
+        carol.send(ClientMessage::StartedHosting(3333)).await;
+        assert_eq!(hosts(alice.recv().await), vec![("Carol".to_string(), local(3333))]);
+        carol.recv().await;
+        alice.send(ClientMessage::StartedHosting(1111)).await;
+        assert_eq!(hosts(carol.recv().await), vec![("Alice".to_string(), local(1111))]);
+        bob.send(ClientMessage::StartedHosting(2222)).await;
+        assert_eq!(hosts(carol.recv().await), vec![("Bob".to_string(), local(2222))]);
+
+        bob.send(ClientMessage::StoppedHosting).await;
+        assert_eq!(hosts(carol.recv().await), vec![("Alice".to_string(), local(1111))]);
+        drop(alice);
+        assert_eq!(hosts(carol.recv().await), vec![("Carol".to_string(), local(3333))]);
+    });
+}
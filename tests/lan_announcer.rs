@@ -0,0 +1,118 @@
+//! Hive games announced as LAN worlds.
+
+use std::net::{Ipv4Addr, SocketAddr};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use async_std::channel::{unbounded, Sender};
+use async_std::future::timeout;
+use async_std::net::{TcpListener, TcpStream, UdpSocket};
+use async_std::prelude::*;
+use async_std::task::{block_on, spawn};
+
+use hive_search::lan_announcer::lan_announcer;
+use hive_search::lan_listener::parse;
+use hive_search::messages::LanGame;
+use hive_search::nbt_editor::{NbtInstruction, MARKER};
+use hive_search::sync::PauseToken;
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Starts an announcer aimed at a loopback socket.
+async fn announcer(stop: &Arc<PauseToken>) -> (UdpSocket, Sender<NbtInstruction>) {
+    let socket = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+    let (instructions, instruction_recv) = unbounded();
+    spawn(lan_announcer(
+        stop.clone(),
+        Arc::new(PauseToken::new(false)),
+        instruction_recv,
+        socket.local_addr().unwrap(),
+    ));
+    (socket, instructions)
+}
+
+fn game(nickname: &str, address: SocketAddr) -> LanGame {
+    LanGame { id: 0, nickname: nickname.to_string(), address, opened_at: SystemTime::now() }
+}
+
+async fn expect_announcement(socket: &UdpSocket) -> (String, u16) {
+    let mut buffer = [0u8; 1024];
+    let (length, _) = timeout(TIMEOUT, socket.recv_from(&mut buffer)).await.expect("nothing announced").unwrap();
+    parse(&String::from_utf8_lossy(&buffer[..length])).expect("malformed announcement")
+}
+
+#[test]
+fn game_is_announced_with_the_nickname() {
+    block_on(async {
+        let stop = Arc::new(PauseToken::new(true));
+        let (socket, instructions) = announcer(&stop).await;
+        let world = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+        instructions.send(NbtInstruction::SetToOneHost(game("Alice", world.local_addr().unwrap()))).await.unwrap();
+
+        let (motd, _) = expect_announcement(&socket).await;
+        assert!(motd.starts_with(MARKER));
+        assert!(motd.ends_with("Alice's Game"));
+        stop.resume().await;
+    });
+}
+
+#[test]
+fn proxy_reaches_the_game() {
+    block_on(async {
+        let stop = Arc::new(PauseToken::new(true));
+        let (socket, instructions) = announcer(&stop).await;
+        let world = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+        instructions.send(NbtInstruction::SetToOneHost(game("Alice", world.local_addr().unwrap()))).await.unwrap();
+        let (_, port) = expect_announcement(&socket).await;
+
+        let mut player = TcpStream::connect((Ipv4Addr::LOCALHOST, port)).await.unwrap();
+        player.write_all(b"hello").await.unwrap();
+        let (mut host, _) = timeout(TIMEOUT, world.accept()).await.unwrap().unwrap();
+        let mut buffer = [0u8; 5];
+        host.read_exact(&mut buffer).await.unwrap();
+        assert_eq!(&buffer, b"hello");
+
+        host.write_all(b"world").await.unwrap();
+        player.read_exact(&mut buffer).await.unwrap();
+        assert_eq!(&buffer, b"world");
+        stop.resume().await;
+    });
+}
+
+#[test]
+fn every_game_is_announced() {
+    block_on(async {
+        let stop = Arc::new(PauseToken::new(true));
+        let (socket, instructions) = announcer(&stop).await;
+        let games = vec![
+            game("Alice", SocketAddr::from((Ipv4Addr::LOCALHOST, 1000))),
+            game("Bob", SocketAddr::from((Ipv4Addr::LOCALHOST, 2000))),
+        ];
+        instructions.send(NbtInstruction::SetToManyHosts(games)).await.unwrap();
+
+        let (first, _) = expect_announcement(&socket).await;
+        let (second, _) = expect_announcement(&socket).await;
+        let mut motds = [first, second];
+        motds.sort();
+        assert!(motds[0].ends_with("Alice's Game"));
+        assert!(motds[1].ends_with("Bob's Game"));
+        stop.resume().await;
+    });
+}
+
+#[test]
+fn closed_game_is_no_longer_announced() {
+    block_on(async {
+        let stop = Arc::new(PauseToken::new(true));
+        let (socket, instructions) = announcer(&stop).await;
+        instructions.send(NbtInstruction::SetToOneHost(game("Alice", SocketAddr::from((Ipv4Addr::LOCALHOST, 1000))))).await.unwrap();
+        expect_announcement(&socket).await;
+
+        instructions.send(NbtInstruction::SetToNoHost).await.unwrap();
+        // An announcement might have been on its way.
+        let mut buffer = [0u8; 1024];
+        while timeout(Duration::from_millis(200), socket.recv_from(&mut buffer)).await.is_ok() {}
+        assert!(timeout(Duration::from_secs(2), socket.recv_from(&mut buffer)).await.is_err());
+        stop.resume().await;
+    });
+}
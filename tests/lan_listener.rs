@@ -0,0 +1,104 @@
+//! Minecraft's LAN announcements.
+
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_std::channel::{unbounded, Receiver};
+use async_std::future::timeout;
+use async_std::net::UdpSocket;
+use async_std::task::{block_on, spawn};
+
+use hive_search::lan_listener::{lan_listener, parse};
+use hive_search::log_reader::ClientChange;
+use hive_search::nbt_editor::MARKER;
+use hive_search::sync::PauseToken;
+
+/// Starts a listener on a loopback port, trusting only the given addresses.
+async fn listener(stop: &Arc<PauseToken>, local: Vec<IpAddr>) -> (SocketAddr, Receiver<ClientChange>) {
+    let socket = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+    let address = socket.local_addr().unwrap();
+    let (log_sink, changes) = unbounded();
+    spawn(lan_listener(stop.clone(), Arc::new(PauseToken::new(false)), socket, local, log_sink));
+    (address, changes)
+}
+
+async fn announce(target: SocketAddr, port: u16) {
+    announce_as(target, "Alice - New World", port).await;
+}
+
+async fn announce_as(target: SocketAddr, motd: &str, port: u16) {
+    let socket = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+    let packet = format!("[MOTD]{}[/MOTD][AD]{}[/AD]", motd, port);
+    socket.send_to(packet.as_bytes(), target).await.unwrap();
+}
+
+async fn expect_change(changes: &Receiver<ClientChange>, wait: Duration) -> ClientChange {
+    timeout(wait, changes.recv()).await.expect("no change reported").unwrap()
+}
+
+#[test]
+fn announcements_are_parsed() {
+    assert_eq!(parse("[MOTD]New World[/MOTD][AD]51234[/AD]"), Some(("New World".to_string(), 51234)));
+    assert_eq!(parse("[MOTD]New World[/MOTD][AD]192.168.0.2:51234[/AD]"), Some(("New World".to_string(), 51234)));
+    assert_eq!(parse("[MOTD][/MOTD][AD]25565[/AD]"), Some((String::new(), 25565)));
+}
+
+#[test]
+fn malformed_announcements_are_rejected() {
+    assert_eq!(parse("[MOTD]New World[/MOTD]"), None);
+    assert_eq!(parse("[MOTD]New World[/MOTD][AD]port[/AD]"), None);
+    assert_eq!(parse("[AD]51234"), None);
+    assert_eq!(parse(""), None);
+}
+
+#[test]
+fn opened_world_is_reported_once() {
+    block_on(async {
+        let stop = Arc::new(PauseToken::new(true));
+        let (address, changes) = listener(&stop, vec![IpAddr::V4(Ipv4Addr::LOCALHOST)]).await;
+        announce(address, 51234).await;
+        assert!(matches!(expect_change(&changes, Duration::from_secs(1)).await, ClientChange::StartedHosting(51234)));
+
+        announce(address, 51234).await;
+        assert!(timeout(Duration::from_millis(300), changes.recv()).await.is_err());
+
+        announce(address, 51235).await;
+        assert!(matches!(expect_change(&changes, Duration::from_secs(1)).await, ClientChange::StartedHosting(51235)));
+        stop.resume().await;
+    });
+}
+
+#[test]
+fn silent_world_is_closed() {
+    block_on(async {
+        let stop = Arc::new(PauseToken::new(true));
+        let (address, changes) = listener(&stop, vec![IpAddr::V4(Ipv4Addr::LOCALHOST)]).await;
+        announce(address, 51234).await;
+        assert!(matches!(expect_change(&changes, Duration::from_secs(1)).await, ClientChange::StartedHosting(51234)));
+        assert!(matches!(expect_change(&changes, Duration::from_secs(10)).await, ClientChange::StoppedHosting));
+        stop.resume().await;
+    });
+}
+
+#[test]
+fn other_machines_are_ignored() {
+    block_on(async {
+        let stop = Arc::new(PauseToken::new(true));
+        let (address, changes) = listener(&stop, vec![IpAddr::V4(Ipv4Addr::new(192, 168, 0, 2))]).await;
+        announce(address, 51234).await;
+        assert!(timeout(Duration::from_millis(500), changes.recv()).await.is_err());
+        stop.resume().await;
+    });
+}
+
+#[test]
+fn announced_hive_games_are_ignored() {
+    block_on(async {
+        let stop = Arc::new(PauseToken::new(true));
+        let (address, changes) = listener(&stop, vec![IpAddr::V4(Ipv4Addr::LOCALHOST)]).await;
+        announce_as(address, &format!("{}HiveSearch: Bob's Game", MARKER), 51234).await;
+        assert!(timeout(Duration::from_millis(500), changes.recv()).await.is_err());
+        stop.resume().await;
+    });
+}
@@ -0,0 +1,106 @@
+//! A crowded hive, handling a message should not get slower with every client.
+
+mod common;
+
+use std::time::{Duration, Instant};
+
+use async_std::task::block_on;
+use futures::future::join_all;
+
+use hive_search::messages::{ChatContent, ClientMessage, ServerMessage};
+
+use common::*;
+
+const CLIENTS: usize = 300;
+
+/// Clients of the small hive to compare with.
+const FEW_CLIENTS: usize = 3;
+
+/// How much slower a message may get in the crowded hive, the timing is noisy.
+const HEADROOM: f64 = 4.;
+
+/// Messages timed in each hive.
+const MESSAGES: usize = 1000;
+
+/// Latency reported over and over, unchanged so that nobody is told.
+const LATENCY: Duration = Duration::from_millis(40);
+
+/// Joins the clients one by one, each gets the game list.
+async fn crowd(server: &TestServer, count: usize) -> Vec<ScriptedClient> {
+    let mut clients = Vec::with_capacity(count);
+    for index in 0..count {
+        let mut client = ScriptedClient::join(server, &format!("Bee{}", index)).await;
+        assert!(matches!(client.recv().await, ServerMessage::NoHost));
+        clients.push(client);
+    }
+    clients
+}
+
+/// Waits for the chat message with the text.
+async fn expect_chat(client: &mut ScriptedClient, text: &str) {
+    match client.recv().await {
+        ServerMessage::Chat(line) => assert_eq!(line.content, ChatContent::Text(text.to_string())),
+        message => panic!("expected {:?}, got {:?}", text, message),
+    }
+}
+
+/// Time for the hive to handle a message of the first client that is not passed on.
+async fn quiet_message(clients: &mut [ScriptedClient]) -> Duration {
+    clients[0].send(ClientMessage::Latency(LATENCY)).await;
+    let started = Instant::now();
+    for _ in 0..MESSAGES {
+        clients[0].send(ClientMessage::Latency(LATENCY)).await;
+    }
+    // Handled in order, so the chat comes back after every latency.
+    clients[0].send(ClientMessage::Chat(ChatContent::Text("done".to_string()))).await;
+    expect_chat(&mut clients[0], "done").await;
+    started.elapsed() / MESSAGES as u32
+}
+
+#[test]
+fn every_client_is_heard_at_once() {
+    block_on(async {
+        let server = TestServer::start().await;
+        let mut clients = crowd(&server, CLIENTS).await;
+
+        // All messages arrive at once, none gets lost while another is handled.
+        for (index, client) in clients.iter_mut().enumerate() {
+            client.send(ClientMessage::Chat(ChatContent::Text(index.to_string()))).await;
+        }
+        let received = join_all(clients.iter_mut().map(|client| async move {
+            let mut texts = Vec::with_capacity(CLIENTS);
+            for _ in 0..CLIENTS {
+                match client.recv().await {
+                    ServerMessage::Chat(line) => texts.push(line.content.to_string()),
+                    message => panic!("expected a chat message, got {:?}", message),
+                }
+            }
+            texts
+        }))
+        .await;
+        for texts in &received {
+            // Everyone sees the same order.
+            assert_eq!(texts, &received[0]);
+        }
+        let mut texts = received[0].clone();
+        texts.sort_by_key(|text| text.parse::<usize>().unwrap());
+        assert_eq!(texts, (0..CLIENTS).map(|index| index.to_string()).collect::<Vec<_>>());
+    });
+}
+
+#[test]
+fn crowd_does_not_slow_down_the_hive() {
+    block_on(async {
+        let server = TestServer::start().await;
+        let mut few = crowd(&server, FEW_CLIENTS).await;
+        let few_time = quiet_message(&mut few).await;
+
+        let server = TestServer::start().await;
+        let mut many = crowd(&server, CLIENTS).await;
+        let many_time = quiet_message(&mut many).await;
+
+        // Costs the same whether 3 or 300 clients are connected.
+        let ratio = many_time.as_secs_f64() / few_time.as_secs_f64();
+        assert!(ratio <= HEADROOM, "{:?} per message, {:?} with {} clients", many_time, few_time, FEW_CLIENTS);
+    });
+}
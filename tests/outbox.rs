@@ -0,0 +1,74 @@
+//! Outgoing queues of hive clients.
+
+use std::net::SocketAddr;
+use std::time::{Duration, SystemTime};
+
+use async_std::future::timeout;
+use async_std::task::block_on;
+
+use hive_search::messages::{LanGame, ServerMessage};
+use hive_search::outbox;
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+fn game(nickname: &str) -> LanGame {
+    LanGame {
+        id: 1,
+        nickname: nickname.to_string(),
+        address: SocketAddr::from(([127, 0, 0, 1], 25565)),
+        opened_at: SystemTime::now(),
+    }
+}
+
+#[test]
+fn only_the_latest_game_list_is_sent() {
+    block_on(async {
+        let (outbox, inbox) = outbox::outbox(4);
+        assert!(outbox.send(ServerMessage::OneHost(game("Alice"))));
+        assert!(outbox.send(ServerMessage::Ping(1)));
+        assert!(outbox.send(ServerMessage::NoHost));
+        assert!(outbox.send(ServerMessage::OneHost(game("Bob"))));
+
+        match timeout(TIMEOUT, inbox.recv()).await.unwrap() {
+            Some(ServerMessage::OneHost(game)) => assert_eq!(game.nickname, "Bob"),
+            message => panic!("expected Bob's game, got {:?}", message),
+        }
+        assert!(matches!(inbox.recv().await, Some(ServerMessage::Ping(1))));
+        assert!(inbox.try_recv().is_none());
+    });
+}
+
+#[test]
+fn full_queue_drops_the_client() {
+    block_on(async {
+        let (outbox, inbox) = outbox::outbox(2);
+        assert!(outbox.send(ServerMessage::Ping(1)));
+        assert!(outbox.send(ServerMessage::Ping(2)));
+        assert!(!outbox.is_dropped());
+
+        assert!(!outbox.send(ServerMessage::Ping(3)));
+        assert!(outbox.is_dropped());
+        assert!(inbox.is_dropped());
+        // Nothing is sent anymore, not even the game list.
+        assert!(!outbox.send(ServerMessage::NoHost));
+        assert!(timeout(TIMEOUT, inbox.recv()).await.unwrap().is_none());
+        timeout(TIMEOUT, inbox.dropped()).await.unwrap();
+    });
+}
+
+#[test]
+fn queue_is_sent_after_the_outbox_is_gone() {
+    block_on(async {
+        let (outbox, inbox) = outbox::outbox(4);
+        outbox.send(ServerMessage::Ping(1));
+        outbox.send(ServerMessage::NoHost);
+        outbox.send(ServerMessage::Kicked(false));
+        drop(outbox);
+
+        assert!(matches!(inbox.recv().await, Some(ServerMessage::NoHost)));
+        assert!(matches!(inbox.recv().await, Some(ServerMessage::Ping(1))));
+        assert!(matches!(inbox.recv().await, Some(ServerMessage::Kicked(false))));
+        assert!(timeout(TIMEOUT, inbox.recv()).await.unwrap().is_none());
+        assert!(!inbox.is_dropped());
+    });
+}
@@ -0,0 +1,187 @@
+//! Games relayed through the hive.
+
+mod common;
+
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::time::SystemTime;
+
+use async_std::future::timeout;
+use async_std::net::TcpStream;
+use async_std::prelude::*;
+use async_std::task::block_on;
+
+use hive_search::messages::{ClientMessage, LanGame, ServerMessage};
+use hive_search::server::ServerConfig;
+
+use common::*;
+
+async fn relayed_server() -> TestServer {
+    TestServer::with_config(ServerConfig { relay: true, ..config() }).await
+}
+
+/// Opens a game and returns the port the hive relays it on.
+async fn open_game(host: &mut ScriptedClient) -> u16 {
+    host.send(ClientMessage::StartedHosting(25565)).await;
+    let games = hosts(host.recv().await);
+    assert_eq!(games.len(), 1);
+    let (_, address) = games[0];
+    assert!(address.ip().is_unspecified());
+    address.port()
+}
+
+async fn expect_open(host: &mut ScriptedClient) -> u64 {
+    match host.recv().await {
+        ServerMessage::RelayOpen(id) => id,
+        message => panic!("expected a relayed connection, got {:?}", message),
+    }
+}
+
+#[test]
+fn relayed_game_points_at_the_hive() {
+    block_on(async {
+        let server = relayed_server().await;
+        let mut alice = ScriptedClient::join(&server, "Alice").await;
+        assert!(matches!(alice.recv().await, ServerMessage::NoHost));
+        let port = open_game(&mut alice).await;
+        assert_ne!(port, 25565);
+
+        let mut bob = ScriptedClient::join(&server, "Bob").await;
+        let games = hosts(bob.recv().await);
+        assert_eq!(games, vec![("Alice".to_string(), SocketAddr::from((Ipv4Addr::UNSPECIFIED, port)))]);
+    });
+}
+
+#[test]
+fn traffic_is_piped_both_ways() {
+    block_on(async {
+        let server = relayed_server().await;
+        let mut alice = ScriptedClient::join(&server, "Alice").await;
+        alice.recv().await;
+        let port = open_game(&mut alice).await;
+
+        let mut player = TcpStream::connect(local(port)).await.unwrap();
+        let id = expect_open(&mut alice).await;
+        player.write_all(b"hello").await.unwrap();
+        match alice.recv().await {
+            ServerMessage::RelayData(data_id, data) => {
+                assert_eq!(data_id, id);
+                assert_eq!(data, b"hello");
+            }
+            message => panic!("expected game traffic, got {:?}", message),
+        }
+
+        alice.send(ClientMessage::RelayData(id, b"world".to_vec())).await;
+        let mut buffer = [0u8; 5];
+        timeout(TIMEOUT, player.read_exact(&mut buffer)).await.unwrap().unwrap();
+        assert_eq!(&buffer, b"world");
+    });
+}
+
+#[test]
+fn host_closes_the_connection() {
+    block_on(async {
+        let server = relayed_server().await;
+        let mut alice = ScriptedClient::join(&server, "Alice").await;
+        alice.recv().await;
+        let port = open_game(&mut alice).await;
+
+        let mut player = TcpStream::connect(local(port)).await.unwrap();
+        let id = expect_open(&mut alice).await;
+        alice.send(ClientMessage::RelayClose(id)).await;
+        let mut buffer = [0u8; 1];
+        let read = timeout(TIMEOUT, player.read(&mut buffer)).await.unwrap();
+        assert!(matches!(read, Ok(0) | Err(_)));
+    });
+}
+
+#[test]
+fn player_disconnect_is_reported() {
+    block_on(async {
+        let server = relayed_server().await;
+        let mut alice = ScriptedClient::join(&server, "Alice").await;
+        alice.recv().await;
+        let port = open_game(&mut alice).await;
+
+        let player = TcpStream::connect(local(port)).await.unwrap();
+        let id = expect_open(&mut alice).await;
+        drop(player);
+        assert!(matches!(alice.recv().await, ServerMessage::RelayClose(closed) if closed == id));
+    });
+}
+
+#[test]
+fn player_that_falls_behind_is_dropped() {
+    block_on(async {
+        let server = relayed_server().await;
+        let mut alice = ScriptedClient::join(&server, "Alice").await;
+        alice.recv().await;
+        let port = open_game(&mut alice).await;
+
+        // Never reads, the hive stops buffering for it once the socket buffers are full.
+        let _player = TcpStream::connect(local(port)).await.unwrap();
+        let id = expect_open(&mut alice).await;
+        // 8 MiB, well past the socket buffers and the link queue.
+        for _ in 0..512 {
+            alice.send(ClientMessage::RelayData(id, vec![0; 16 * 1024])).await;
+        }
+        assert!(matches!(alice.recv().await, ServerMessage::RelayClose(closed) if closed == id));
+    });
+}
+
+#[test]
+fn closed_game_closes_the_relay() {
+    block_on(async {
+        let server = relayed_server().await;
+        let mut alice = ScriptedClient::join(&server, "Alice").await;
+        alice.recv().await;
+        let port = open_game(&mut alice).await;
+
+        alice.send(ClientMessage::StoppedHosting).await;
+        assert!(matches!(alice.recv().await, ServerMessage::NoHost));
+        async_std::task::sleep(QUIET).await;
+        assert!(TcpStream::connect(local(port)).await.is_err());
+    });
+}
+
+#[test]
+fn repeated_announcement_keeps_the_relay() {
+    block_on(async {
+        let server = relayed_server().await;
+        let mut alice = ScriptedClient::join(&server, "Alice").await;
+        alice.recv().await;
+        open_game(&mut alice).await;
+
+        alice.send(ClientMessage::StartedHosting(25565)).await;
+        alice.expect_silence().await;
+    });
+}
+
+#[test]
+fn other_clients_cannot_inject_traffic() {
+    block_on(async {
+        let server = relayed_server().await;
+        let mut alice = ScriptedClient::join(&server, "Alice").await;
+        alice.recv().await;
+        let port = open_game(&mut alice).await;
+        let mut mallory = ScriptedClient::join(&server, "Mallory").await;
+        mallory.recv().await;
+
+        let mut player = TcpStream::connect(local(port)).await.unwrap();
+        let id = expect_open(&mut alice).await;
+        mallory.send(ClientMessage::RelayData(id, b"evil".to_vec())).await;
+        let mut buffer = [0u8; 4];
+        assert!(timeout(QUIET, player.read_exact(&mut buffer)).await.is_err());
+    });
+}
+
+#[test]
+fn relayed_games_resolve_to_the_hive() {
+    let hive = IpAddr::V4(Ipv4Addr::new(203, 0, 113, 7));
+    let game = |address: SocketAddr| LanGame { id: 1, nickname: "Alice".to_string(), address, opened_at: SystemTime::now() };
+
+    let relayed = game(SocketAddr::from((Ipv4Addr::UNSPECIFIED, 40000))).resolve(hive);
+    assert_eq!(relayed.address, SocketAddr::new(hive, 40000));
+
+    let direct = game(SocketAddr::from((Ipv4Addr::new(192, 168, 0, 2), 25565))).resolve(hive);
+    assert_eq!(direct.address, SocketAddr::from((Ipv4Addr::new(192, 168, 0, 2), 25565)));
+}
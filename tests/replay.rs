@@ -0,0 +1,154 @@
+//! Recorded sessions replay to what the clients were actually told.
+
+mod common;
+
+use std::collections::HashMap;
+
+use async_std::task::block_on;
+
+use hive_search::messages::{ClientMessage, ServerMessage};
+use hive_search::recording::{self, StateEvent};
+use hive_search::server::{self, HostPolicy, ServerCommand, ServerConfig};
+
+use common::*;
+
+/// Session touching every kind of state change.
+/// Returns what each client received, by nickname.
+async fn scripted_session(config: ServerConfig) -> HashMap<&'static str, Vec<ServerMessage>> {
+    let server = TestServer::with_config(config).await;
+    let mut received: HashMap<&str, Vec<ServerMessage>> = HashMap::new();
+
+    let mut alice = ScriptedClient::join(&server, "Alice").await;
+    received.entry("Alice").or_default().push(alice.recv().await);
+    let mut bob = ScriptedClient::join(&server, "Bob").await;
+    received.entry("Bob").or_default().push(bob.recv().await);
+    let members = server.expect_members(&["Alice", "Bob"]).await;
+
+    alice.send(ClientMessage::StartedHosting(25565)).await;
+    received.entry("Alice").or_default().push(alice.recv().await);
+    received.entry("Bob").or_default().push(bob.recv().await);
+
+    bob.send(ClientMessage::StartedHosting(25566)).await;
+    received.entry("Alice").or_default().push(alice.recv().await);
+    received.entry("Bob").or_default().push(bob.recv().await);
+
+    bob.send(ClientMessage::TimerStart(1_000_000)).await;
+    received.entry("Alice").or_default().push(alice.recv().await);
+    received.entry("Bob").or_default().push(bob.recv().await);
+
+    server.commands.send(ServerCommand::Kick(members[1].id)).await.unwrap();
+    received.entry("Alice").or_default().push(alice.recv().await);
+    received.entry("Bob").or_default().push(bob.recv().await);
+    server.expect_members(&["Alice"]).await;
+
+    let mut carol = ScriptedClient::join(&server, "Carol").await;
+    received.entry("Carol").or_default().push(carol.recv().await);
+    received.entry("Carol").or_default().push(carol.recv().await);
+    server.expect_members(&["Alice", "Carol"]).await;
+
+    drop(alice);
+    received.entry("Carol").or_default().push(carol.recv().await);
+    server.expect_members(&["Carol"]).await;
+    received
+}
+
+#[test]
+fn replay_matches_the_live_session() {
+    block_on(async {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("session.rec");
+        let live = scripted_session(ServerConfig {
+            recording: Some(path.clone()),
+            policy: HostPolicy::MostRecent,
+            ..config()
+        })
+        .await;
+
+        let records = recording::load(&path).unwrap();
+        assert!(matches!(records[0].event, StateEvent::Started(_)));
+        assert!(matches!(records[1].event, StateEvent::Policy(HostPolicy::MostRecent)));
+        let mut nicknames = HashMap::new();
+        let mut replayed: HashMap<String, Vec<ServerMessage>> = HashMap::new();
+        for event in server::replay(records).await {
+            if let StateEvent::Joined(id, nickname, _, _) = &event.record.event {
+                nicknames.insert(*id, nickname.clone());
+            }
+            // Live clients skip roster updates.
+            for (id, message) in event.sent.into_iter().filter(|(_, message)| !is_roster(message)) {
+                replayed.entry(nicknames[&id].clone()).or_default().push(message);
+            }
+        }
+
+        assert_eq!(live.len(), replayed.len());
+        for (nickname, messages) in live {
+            assert_eq!(
+                format!("{:?}", messages),
+                format!("{:?}", replayed[nickname]),
+                "{} was told something else",
+                nickname
+            );
+        }
+    });
+}
+
+#[test]
+fn host_key_is_not_recorded() {
+    block_on(async {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("session.rec");
+        let server = TestServer::with_config(ServerConfig {
+            recording: Some(path.clone()),
+            host_key: Some(7),
+            ..config()
+        })
+        .await;
+        let mut alice = ScriptedClient::join(&server, "Alice").await;
+        alice.recv().await;
+        alice.send(ClientMessage::HostKey(7)).await;
+        server.expect_member_changed("Alice").await;
+
+        let records = recording::load(&path).unwrap();
+        let keys: Vec<u64> = records
+            .iter()
+            .filter_map(|record| match record.event {
+                StateEvent::Message(_, ClientMessage::HostKey(key)) => Some(key),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(keys, vec![0]);
+    });
+}
+
+#[test]
+fn recordings_are_appended() {
+    block_on(async {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("session.rec");
+        for _ in 0..2 {
+            let config = ServerConfig {
+                recording: Some(path.clone()),
+                ..config()
+            };
+            let server = TestServer::with_config(config).await;
+            let mut alice = ScriptedClient::join(&server, "Alice").await;
+            alice.recv().await;
+        }
+
+        let records = recording::load(&path).unwrap();
+        let runs = records
+            .iter()
+            .filter(|record| matches!(record.event, StateEvent::Started(_)))
+            .count();
+        assert_eq!(runs, 2);
+        // Ids start over with every run.
+        let replayed = server::replay(records).await;
+        let joined: Vec<u64> = replayed
+            .iter()
+            .filter_map(|event| match event.record.event {
+                StateEvent::Joined(id, _, _, _) => Some(id),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(joined, vec![1, 1]);
+    });
+}
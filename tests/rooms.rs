@@ -0,0 +1,146 @@
+//! Rooms sharing one hive.
+
+mod common;
+
+use std::time::Duration;
+
+use async_std::future::timeout;
+use async_std::net::TcpStream;
+use async_std::task::{block_on, sleep};
+use futures::{SinkExt, TryStreamExt};
+use serde::Serialize;
+
+use hive_search::codec::BincodeCodec;
+use hive_search::messages::{ChatContent, ClientMessage, PROTOCOL_VERSION, RejectReason, ServerMessage};
+use hive_search::server::ServerConfig;
+use hive_search::timer::TimerState;
+use hive_search::transport;
+
+use common::*;
+
+/// Handshake as sent by builds from before rooms.
+#[derive(Serialize)]
+struct PreRoomsHandshake {
+    protocol_version: u32,
+    app_version: String,
+    nickname: String,
+}
+
+/// First variant of the client messages from before rooms.
+#[derive(Serialize)]
+enum PreRoomsMessage {
+    Handshake(PreRoomsHandshake),
+}
+
+/// Joins the room, fails the test if rejected.
+async fn join_room(server: &TestServer, nickname: &str, room: &str) -> ScriptedClient {
+    match ScriptedClient::try_join_room(server, handshake(nickname), room, "").await {
+        Ok(client) => client,
+        Err(reason) => panic!("{} was rejected: {}", nickname, reason),
+    }
+}
+
+#[test]
+fn rooms_do_not_share_games() {
+    block_on(async {
+        let server = TestServer::start().await;
+        let mut alice = join_room(&server, "Alice", "red").await;
+        alice.recv().await;
+        let mut bob = join_room(&server, "Bob", "blue").await;
+        bob.recv().await;
+        let mut carol = join_room(&server, "Carol", "red").await;
+        carol.recv().await;
+
+        alice.send(ClientMessage::StartedHosting(25565)).await;
+        assert_eq!(hosts(carol.recv().await), vec![("Alice".to_string(), local(25565))]);
+        alice.recv().await;
+        bob.expect_silence().await;
+
+        bob.send(ClientMessage::StartedHosting(25566)).await;
+        assert_eq!(hosts(bob.recv().await), vec![("Bob".to_string(), local(25566))]);
+        alice.expect_silence().await;
+        carol.expect_silence().await;
+    });
+}
+
+#[test]
+fn rooms_do_not_share_the_timer_and_chat() {
+    block_on(async {
+        let server = TestServer::start().await;
+        let mut alice = join_room(&server, "Alice", "red").await;
+        alice.recv().await;
+        let mut bob = join_room(&server, "Bob", "blue").await;
+        bob.recv().await;
+
+        alice.send(ClientMessage::TimerStart(1_000_000)).await;
+        assert!(matches!(alice.recv().await, ServerMessage::Timer(TimerState::Running(1_000_000))));
+        alice.send(ClientMessage::Chat(ChatContent::Text("go".to_string()))).await;
+        assert!(matches!(alice.recv().await, ServerMessage::Chat(_)));
+        bob.expect_silence().await;
+
+        // The default room is a room like any other.
+        let mut dave = ScriptedClient::join(&server, "Dave").await;
+        assert!(matches!(dave.recv().await, ServerMessage::NoHost));
+        dave.expect_silence().await;
+        server.expect_members(&["Alice", "Bob", "Dave"]).await;
+    });
+}
+
+#[test]
+fn invalid_room_is_rejected() {
+    block_on(async {
+        let server = TestServer::start().await;
+        let result = ScriptedClient::try_join_room(&server, handshake("Alice"), &"x".repeat(33), "").await;
+        assert!(matches!(result, Err(RejectReason::InvalidRoom)));
+    });
+}
+
+#[test]
+fn pre_rooms_client_is_told_the_versions_differ() {
+    block_on(async {
+        let server = TestServer::start().await;
+        let stream = TcpStream::connect(server.address).await.unwrap();
+        let codec = BincodeCodec::<PreRoomsMessage, ServerMessage>::new();
+        let mut stream = asynchronous_codec::Framed::new(transport::plain(stream), codec);
+        let handshake = PreRoomsHandshake {
+            protocol_version: 10,
+            app_version: "0.1.0".to_string(),
+            nickname: "Alice".to_string(),
+        };
+        stream.send(PreRoomsMessage::Handshake(handshake)).await.unwrap();
+        let reply = timeout(TIMEOUT, stream.try_next()).await.unwrap().unwrap();
+        assert!(matches!(
+            reply,
+            Some(ServerMessage::Rejected(RejectReason::ProtocolMismatch(PROTOCOL_VERSION, _)))
+        ));
+    });
+}
+
+#[test]
+fn empty_room_expires() {
+    block_on(async {
+        let server = TestServer::with_config(ServerConfig {
+            room_expiry: Duration::from_millis(500),
+            ..config()
+        })
+        .await;
+        let mut alice = join_room(&server, "Alice", "red").await;
+        alice.recv().await;
+        alice.send(ClientMessage::TimerStart(1_000_000)).await;
+        alice.recv().await;
+        drop(alice);
+        server.expect_user_count(0).await;
+
+        // Coming back in time finds the room as it was.
+        let mut bob = join_room(&server, "Bob", "red").await;
+        assert!(matches!(bob.recv().await, ServerMessage::NoHost));
+        assert!(matches!(bob.recv().await, ServerMessage::Timer(TimerState::Running(1_000_000))));
+        drop(bob);
+        server.expect_user_count(0).await;
+
+        sleep(Duration::from_secs(1)).await;
+        let mut carol = join_room(&server, "Carol", "red").await;
+        assert!(matches!(carol.recv().await, ServerMessage::NoHost));
+        carol.expect_silence().await;
+    });
+}
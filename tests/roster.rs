@@ -0,0 +1,111 @@
+//! Roster of the searchers in a room.
+
+mod common;
+
+use std::time::Duration;
+
+use async_std::task::block_on;
+
+use hive_search::messages::{ClientMessage, Member, ServerMessage};
+
+use common::*;
+
+/// Members of a full roster.
+fn roster(message: ServerMessage) -> Vec<Member> {
+    match message {
+        ServerMessage::Roster(members) => members,
+        message => panic!("expected a roster, got {:?}", message),
+    }
+}
+
+/// Member of a roster change.
+fn changed(message: ServerMessage) -> Member {
+    match message {
+        ServerMessage::MemberChanged(member) => member,
+        message => panic!("expected a member change, got {:?}", message),
+    }
+}
+
+#[test]
+fn joining_client_gets_the_roster() {
+    block_on(async {
+        let server = TestServer::start().await;
+        let mut alice = ScriptedClient::join(&server, "Alice").await;
+        let members = roster(alice.recv_roster().await);
+        assert_eq!(members.len(), 1);
+        assert_eq!(members[0].nickname, "Alice");
+        assert!(members[0].ip.is_loopback());
+        assert_eq!(members[0].hosting, None);
+
+        let mut bob = ScriptedClient::join(&server, "Bob").await;
+        let members = roster(bob.recv_roster().await);
+        let nicknames: Vec<&str> = members.iter().map(|member| member.nickname.as_str()).collect();
+        assert_eq!(nicknames, vec!["Alice", "Bob"]);
+        assert_eq!(changed(alice.recv_roster().await), members[1]);
+    });
+}
+
+#[test]
+fn games_and_latency_update_the_member() {
+    block_on(async {
+        let server = TestServer::start().await;
+        let mut alice = ScriptedClient::join(&server, "Alice").await;
+        alice.recv_roster().await;
+        let mut bob = ScriptedClient::join(&server, "Bob").await;
+        bob.recv_roster().await;
+        alice.recv_roster().await;
+
+        alice.send(ClientMessage::StartedHosting(25565)).await;
+        let member = changed(bob.recv_roster().await);
+        assert_eq!((member.nickname.as_str(), member.hosting), ("Alice", Some(25565)));
+        assert_eq!(changed(alice.recv_roster().await), member);
+
+        alice.send(ClientMessage::Latency(Duration::from_millis(40))).await;
+        let member = changed(bob.recv_roster().await);
+        assert_eq!(member.latency, Some(Duration::from_millis(40)));
+        assert_eq!(member.hosting, Some(25565));
+
+        alice.send(ClientMessage::StoppedHosting).await;
+        assert_eq!(changed(bob.recv_roster().await).hosting, None);
+        // The host hears of every change too.
+        loop {
+            let member = server.expect_member_changed("Alice").await;
+            if member.latency.is_some() && member.hosting.is_none() {
+                break
+            }
+        }
+    });
+}
+
+#[test]
+fn leaving_is_announced() {
+    block_on(async {
+        let server = TestServer::start().await;
+        let mut alice = ScriptedClient::join(&server, "Alice").await;
+        alice.recv_roster().await;
+        let mut bob = ScriptedClient::join(&server, "Bob").await;
+        let id = roster(bob.recv_roster().await)[1].id;
+        alice.recv_roster().await;
+
+        drop(bob);
+        assert!(matches!(alice.recv_roster().await, ServerMessage::MemberLeft(left) if left == id));
+    });
+}
+
+#[test]
+fn rooms_have_their_own_roster() {
+    block_on(async {
+        let server = TestServer::start().await;
+        let red = |nickname| ScriptedClient::try_join_room(&server, handshake(nickname), "red", "");
+        let mut alice = red("Alice").await.unwrap();
+        alice.recv_roster().await;
+        let mut bob = ScriptedClient::join(&server, "Bob").await;
+        let members = roster(bob.recv_roster().await);
+        assert_eq!(members.len(), 1);
+        assert_eq!(members[0].nickname, "Bob");
+
+        // Bob never shows up in the red room.
+        let _carol = red("Carol").await.unwrap();
+        assert_eq!(changed(alice.recv_roster().await).nickname, "Carol");
+    });
+}
@@ -0,0 +1,225 @@
+//! The status API serves what the session observer saw.
+
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use async_std::future::timeout;
+use async_std::io::prelude::BufReadExt;
+use async_std::io::{BufReader, ReadExt, WriteExt};
+use async_std::net::TcpStream;
+use async_std::task::block_on;
+
+use hive_search::messages::{ChatContent, ChatLine, LanGame, Member, QuickPing};
+use hive_search::observer::{SessionEvent, SessionObserver};
+use hive_search::status::{self, ChatStatus, GameStatus, Snapshot, StatusObserver, TimerStatus};
+use hive_search::sync::PauseToken;
+use hive_search::timer::TimerView;
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Status API on an ephemeral localhost port.
+/// Returns the stop token, the API stops when it is resumed.
+async fn start(status: Arc<StatusObserver>) -> (SocketAddr, Arc<PauseToken>) {
+    let stop_token = Arc::new(PauseToken::new(true));
+    let address = status::serve(stop_token.clone(), "127.0.0.1:0".parse().unwrap(), status)
+        .await
+        .unwrap();
+    (address, stop_token)
+}
+
+/// Sends a request, returns the status line and the body.
+async fn get(address: SocketAddr, path: &str) -> (String, String) {
+    let mut stream = TcpStream::connect(address).await.unwrap();
+    let request = format!("GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path);
+    stream.write_all(request.as_bytes()).await.unwrap();
+    let mut response = String::new();
+    timeout(TIMEOUT, stream.read_to_string(&mut response)).await.unwrap().unwrap();
+    let (head, body) = response.split_once("\r\n\r\n").unwrap();
+    (head.lines().next().unwrap().to_string(), body.to_string())
+}
+
+/// Reads the next server-sent event, skips comments.
+async fn next_event(reader: &mut BufReader<TcpStream>) -> (String, String) {
+    let mut name = String::new();
+    let mut data = String::new();
+    loop {
+        let mut line = String::new();
+        timeout(TIMEOUT, reader.read_line(&mut line)).await.unwrap().unwrap();
+        let line = line.trim_end();
+        if let Some(value) = line.strip_prefix("event: ") {
+            name = value.to_string();
+        } else if let Some(value) = line.strip_prefix("data: ") {
+            data = value.to_string();
+        } else if line.is_empty() && !name.is_empty() {
+            return (name, data)
+        }
+    }
+}
+
+fn game(nickname: &str, port: u16) -> LanGame {
+    LanGame {
+        id: 1,
+        nickname: nickname.to_string(),
+        address: SocketAddr::from(([127, 0, 0, 1], port)),
+        opened_at: SystemTime::UNIX_EPOCH + Duration::from_secs(1),
+    }
+}
+
+#[test]
+fn snapshot_follows_the_events() {
+    block_on(async {
+        let status = Arc::new(StatusObserver::new());
+        let (address, _stop_token) = start(status.clone()).await;
+
+        let (line, body) = get(address, "/status").await;
+        assert_eq!(line, "HTTP/1.1 200 OK");
+        assert_eq!(serde_json::from_str::<Snapshot>(&body).unwrap(), Snapshot::default());
+
+        status.notify(SessionEvent::Members(vec![Member {
+            id: 1,
+            nickname: "Alice".to_string(),
+            ip: "127.0.0.1".parse().unwrap(),
+            connected_since: SystemTime::now(),
+            hosting: Some(25565),
+            latency: None,
+            host: false,
+        }]));
+        status.notify(SessionEvent::LanGames(vec![game("Alice", 25565)]));
+        status.notify(SessionEvent::Timer(TimerView::Stopped(Duration::from_millis(61_500))));
+        status.notify(SessionEvent::Latency(Duration::from_millis(42)));
+
+        let (_, body) = get(address, "/status").await;
+        let snapshot: Snapshot = serde_json::from_str(&body).unwrap();
+        assert_eq!(snapshot.users, vec!["Alice".to_string()]);
+        assert_eq!(
+            snapshot.games,
+            vec![GameStatus {
+                nickname: "Alice".to_string(),
+                address: "127.0.0.1:25565".to_string(),
+                opened_at: 1000,
+            }]
+        );
+        assert_eq!(snapshot.timer, TimerStatus::Stopped { time_ms: 61_500 });
+        assert_eq!(snapshot.latency_ms, Some(42));
+    });
+}
+
+/// Searcher without a game.
+fn member(id: u64, nickname: &str) -> Member {
+    Member {
+        id,
+        nickname: nickname.to_string(),
+        ip: "127.0.0.1".parse().unwrap(),
+        connected_since: SystemTime::now(),
+        hosting: None,
+        latency: None,
+        host: false,
+    }
+}
+
+#[test]
+fn users_come_from_the_roster_unless_hosting() {
+    let status = StatusObserver::new();
+    status.notify(SessionEvent::Roster(vec![member(1, "Alice"), member(2, "Bob")]));
+    assert_eq!(status.snapshot().users, vec!["Alice".to_string(), "Bob".to_string()]);
+
+    // The host sees every room, its own roster does not override that.
+    status.notify(SessionEvent::Members(vec![member(1, "Alice"), member(2, "Bob"), member(3, "Carol")]));
+    status.notify(SessionEvent::Roster(vec![member(1, "Alice")]));
+    assert_eq!(status.snapshot().users.len(), 3);
+}
+
+#[test]
+fn event_stream_starts_with_the_snapshot() {
+    block_on(async {
+        let status = Arc::new(StatusObserver::new());
+        status.notify(SessionEvent::LanGames(vec![game("Alice", 25565)]));
+        let (address, _stop_token) = start(status.clone()).await;
+
+        let mut stream = TcpStream::connect(address).await.unwrap();
+        stream.write_all(b"GET /events HTTP/1.1\r\nHost: localhost\r\n\r\n").await.unwrap();
+        let mut reader = BufReader::new(stream);
+        let mut status_line = String::new();
+        reader.read_line(&mut status_line).await.unwrap();
+        assert_eq!(status_line.trim_end(), "HTTP/1.1 200 OK");
+
+        let (name, data) = next_event(&mut reader).await;
+        assert_eq!(name, "snapshot");
+        assert_eq!(serde_json::from_str::<Snapshot>(&data).unwrap(), status.snapshot());
+
+        status.notify(SessionEvent::LanGames(Vec::new()));
+        let (name, data) = next_event(&mut reader).await;
+        assert_eq!(name, "games");
+        assert_eq!(data, "[]");
+
+        status.notify(SessionEvent::Chat(ChatLine {
+            nickname: "Bob".to_string(),
+            sent_at: SystemTime::UNIX_EPOCH,
+            content: ChatContent::Ping(QuickPing::FoundVillage),
+        }));
+        let (name, data) = next_event(&mut reader).await;
+        assert_eq!(name, "chat");
+        let chat: ChatStatus = serde_json::from_str(&data).unwrap();
+        assert_eq!(chat.nickname, "Bob");
+        assert_eq!(chat.text, ChatContent::Ping(QuickPing::FoundVillage).to_string());
+    });
+}
+
+#[test]
+fn stopping_closes_the_event_stream() {
+    block_on(async {
+        let status = Arc::new(StatusObserver::new());
+        let (address, stop_token) = start(status).await;
+
+        let mut stream = TcpStream::connect(address).await.unwrap();
+        stream.write_all(b"GET /events HTTP/1.1\r\nHost: localhost\r\n\r\n").await.unwrap();
+        let mut reader = BufReader::new(stream);
+        next_event(&mut reader).await;
+
+        stop_token.resume().await;
+        let mut rest = String::new();
+        timeout(TIMEOUT, reader.read_to_string(&mut rest)).await.unwrap().unwrap();
+        assert!(rest.is_empty());
+    });
+}
+
+#[test]
+fn slow_subscriber_is_dropped() {
+    block_on(async {
+        let status = Arc::new(StatusObserver::new());
+        let (address, _stop_token) = start(status.clone()).await;
+
+        let mut stream = TcpStream::connect(address).await.unwrap();
+        stream.write_all(b"GET /events HTTP/1.1\r\nHost: localhost\r\n\r\n").await.unwrap();
+        let mut reader = BufReader::new(stream);
+        let mut status_line = String::new();
+        reader.read_line(&mut status_line).await.unwrap();
+        next_event(&mut reader).await;
+
+        // More than the socket buffers and the queue hold, while nobody reads.
+        let games: Vec<LanGame> = (0..100).map(|port| game("Alice", port)).collect();
+        let sent = status::SUBSCRIBER_QUEUE_SIZE * 16;
+        for _ in 0..sent {
+            status.notify(SessionEvent::LanGames(games.clone()));
+        }
+
+        let mut rest = String::new();
+        timeout(TIMEOUT, reader.read_to_string(&mut rest)).await.unwrap().unwrap();
+        assert!(rest.matches("event: games").count() < sent);
+    });
+}
+
+#[test]
+fn unknown_paths_and_methods_are_refused() {
+    block_on(async {
+        let (address, _stop_token) = start(Arc::new(StatusObserver::new())).await;
+        assert_eq!(get(address, "/secrets").await.0, "HTTP/1.1 404 Not Found");
+
+        let mut stream = TcpStream::connect(address).await.unwrap();
+        stream.write_all(b"POST /status HTTP/1.1\r\nHost: localhost\r\n\r\n").await.unwrap();
+        let mut response = String::new();
+        timeout(TIMEOUT, stream.read_to_string(&mut response)).await.unwrap().unwrap();
+        assert!(response.starts_with("HTTP/1.1 405"));
+    });
+}